@@ -0,0 +1,513 @@
+use crate::error::DecodeError;
+use crate::instruction::{Instruction, MemoryOperand, Operand, Prefixes, Repeat};
+use crate::opcodes::{OPCODE_TRIE, Opcode};
+use crate::registers::{EACS, REGISTERS, Register, SEGMENT_REGISTERS};
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    start: usize,
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn next(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.pos)
+            .ok_or_else(|| DecodeError::UnexpectedEnd {
+                offset: self.start,
+                bytes: self.consumed(),
+            })?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    // Both ends are clamped, since a caller can start decoding past the end
+    fn consumed(&self) -> Vec<u8> {
+        let len = self.bytes.len();
+        self.bytes[self.start.min(len)..self.pos.min(len)].to_vec()
+    }
+
+    fn invalid(&self, opcode: Opcode) -> DecodeError {
+        DecodeError::InvalidModRm {
+            offset: self.start,
+            bytes: self.consumed(),
+            opcode,
+        }
+    }
+
+    fn unknown(&self) -> DecodeError {
+        DecodeError::UnknownOpcode {
+            offset: self.start,
+            bytes: self.consumed(),
+        }
+    }
+
+    fn next_u16(&mut self) -> Result<u16, DecodeError> {
+        let lo = self.next()?;
+        let hi = self.next()?;
+        Ok(u16::from_le_bytes([lo, hi]))
+    }
+
+    // Immediate data is one byte (sign-extended) or two depending on w
+    fn next_data(&mut self, w: u8) -> Result<i16, DecodeError> {
+        if w == 1 {
+            Ok(self.next_u16()? as i16)
+        } else {
+            Ok(self.next()? as i8 as i16)
+        }
+    }
+}
+
+fn register(index: u8, w: u8) -> Register {
+    REGISTERS[w as usize][(index & 0b111) as usize]
+}
+
+fn decode_rm(cursor: &mut Cursor, modrm: u8, w: u8) -> Result<Operand, DecodeError> {
+    let mode = modrm >> 6;
+    let regormem = modrm & 0b111;
+    let eac = Some(EACS[regormem as usize]);
+
+    let operand = match mode {
+        0b11 => return Ok(Operand::Register(register(regormem, w))),
+        // Special case for 16-bit direct address
+        0b00 if regormem == 0b110 => MemoryOperand::direct(cursor.next_u16()?),
+        0b00 => MemoryOperand {
+            eac,
+            displacement: None,
+        },
+        0b01 => MemoryOperand {
+            eac,
+            displacement: Some(cursor.next()? as i8 as i16),
+        },
+        _ => MemoryOperand {
+            eac,
+            displacement: Some(cursor.next_u16()? as i16),
+        },
+    };
+    Ok(Operand::Memory(operand))
+}
+
+pub fn decode(bytes: &[u8], offset: usize) -> Result<Instruction, DecodeError> {
+    let mut cursor = Cursor {
+        bytes,
+        start: offset,
+        pos: offset,
+    };
+    let mut prefixes = Prefixes::default();
+
+    let (b0, opcode) = loop {
+        let b0 = cursor.next()?;
+        let opcode = OPCODE_TRIE
+            .match_bits(b0)
+            .map(|(opcode, _)| opcode)
+            .ok_or_else(|| cursor.unknown())?;
+
+        match opcode {
+            Opcode::Segment => {
+                prefixes.segment = Some(SEGMENT_REGISTERS[((b0 >> 3) & 0b11) as usize]);
+            }
+            Opcode::Repeat => {
+                prefixes.repeat = Some(if b0 & 0b1 == 1 {
+                    Repeat::Rep
+                } else {
+                    Repeat::Repne
+                });
+            }
+            Opcode::Lock => prefixes.lock = true,
+            _ => break (b0, opcode),
+        }
+    };
+
+    let (opcode, operands, w) = match opcode {
+        Opcode::MovRmR
+        | Opcode::AddRmR
+        | Opcode::SubRmR
+        | Opcode::CmpRmR
+        | Opcode::AndRmR
+        | Opcode::OrRmR
+        | Opcode::XorRmR
+        | Opcode::TestRmR
+        | Opcode::AdcRmR
+        | Opcode::SbbRmR
+        | Opcode::XchgRmR => {
+            // TEST and XCHG have no d bit. Bit 1 is always clear for 84/85, giving
+            // `test rm, reg`, and always set for 86/87, giving `xchg reg, rm`
+            let d = (b0 >> 1) & 0b1;
+            let w = b0 & 0b1;
+            let modrm = cursor.next()?;
+
+            let reg = Operand::Register(register(modrm >> 3, w));
+            let rm = decode_rm(&mut cursor, modrm, w)?;
+            let operands = if d == 1 { [reg, rm] } else { [rm, reg] };
+            (opcode, operands.map(Some), w)
+        }
+        Opcode::MovIR => {
+            let w = (b0 >> 3) & 0b1;
+            let dest = Operand::Register(register(b0, w));
+            let value = cursor.next_data(w)?;
+            (opcode, [Some(dest), Some(Operand::Immediate(value))], w)
+        }
+        Opcode::AddIA
+        | Opcode::SubIA
+        | Opcode::CmpIA
+        | Opcode::AndIA
+        | Opcode::OrIA
+        | Opcode::XorIA
+        | Opcode::TestIA
+        | Opcode::AdcIA
+        | Opcode::SbbIA => {
+            let w = b0 & 0b1;
+            let dest = Operand::Register(register(0b000, w));
+            let value = cursor.next_data(w)?;
+            (opcode, [Some(dest), Some(Operand::Immediate(value))], w)
+        }
+        Opcode::MovIRm => {
+            let w = b0 & 0b1;
+            let modrm = cursor.next()?;
+            if (modrm >> 3) & 0b111 != 0b000 {
+                return Err(cursor.invalid(opcode));
+            }
+
+            let dest = decode_rm(&mut cursor, modrm, w)?;
+            let value = cursor.next_data(w)?;
+            (opcode, [Some(dest), Some(Operand::Immediate(value))], w)
+        }
+        Opcode::AddIRm => {
+            let w = b0 & 0b1;
+            let s = (b0 >> 1) & 0b1;
+            let modrm = cursor.next()?;
+            let opcode = match (modrm >> 3) & 0b111 {
+                0b000 => Opcode::AddIRm,
+                0b001 => Opcode::OrIRm,
+                0b010 => Opcode::AdcIRm,
+                0b011 => Opcode::SbbIRm,
+                0b100 => Opcode::AndIRm,
+                0b101 => Opcode::SubIRm,
+                0b110 => Opcode::XorIRm,
+                0b111 => Opcode::CmpIRm,
+                _ => return Err(cursor.invalid(opcode)),
+            };
+
+            let dest = decode_rm(&mut cursor, modrm, w)?;
+            // With s set a single data byte is sign-extended to the full width
+            let value = cursor.next_data(if s == 1 { 0 } else { w })?;
+            (opcode, [Some(dest), Some(Operand::Immediate(value))], w)
+        }
+        Opcode::TestIRm => {
+            let w = b0 & 0b1;
+            let modrm = cursor.next()?;
+            let opcode = match (modrm >> 3) & 0b111 {
+                0b000 => Opcode::TestIRm,
+                0b010 => Opcode::Not,
+                0b011 => Opcode::Neg,
+                0b100 => Opcode::Mul,
+                0b101 => Opcode::Imul,
+                0b110 => Opcode::Div,
+                0b111 => Opcode::Idiv,
+                _ => return Err(cursor.invalid(opcode)),
+            };
+
+            let dest = decode_rm(&mut cursor, modrm, w)?;
+            let source = match opcode {
+                Opcode::TestIRm => Some(Operand::Immediate(cursor.next_data(w)?)),
+                _ => None,
+            };
+            (opcode, [Some(dest), source], w)
+        }
+        Opcode::Rol => {
+            let v = (b0 >> 1) & 0b1;
+            let w = b0 & 0b1;
+            let modrm = cursor.next()?;
+            let opcode = match (modrm >> 3) & 0b111 {
+                0b000 => Opcode::Rol,
+                0b001 => Opcode::Ror,
+                0b010 => Opcode::Rcl,
+                0b011 => Opcode::Rcr,
+                0b100 => Opcode::Shl,
+                0b101 => Opcode::Shr,
+                0b111 => Opcode::Sar,
+                _ => return Err(cursor.invalid(opcode)),
+            };
+
+            let dest = decode_rm(&mut cursor, modrm, w)?;
+            // v selects between a count of 1 and a count in CL
+            let count = if v == 1 {
+                Operand::Register(Register::CL)
+            } else {
+                Operand::Immediate(1)
+            };
+            (opcode, [Some(dest), Some(count)], w)
+        }
+        Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Lods | Opcode::Stos => {
+            (opcode, [None, None], b0 & 0b1)
+        }
+        Opcode::MovMA | Opcode::MovAM => {
+            let w = b0 & 0b1;
+            let accumulator = Operand::Register(register(0b000, w));
+            let address = Operand::Memory(MemoryOperand::direct(cursor.next_u16()?));
+            let operands = if opcode == Opcode::MovMA {
+                [accumulator, address]
+            } else {
+                [address, accumulator]
+            };
+            (opcode, operands.map(Some), w)
+        }
+        Opcode::MovSr => {
+            let d = (b0 >> 1) & 0b1;
+            let modrm = cursor.next()?;
+            let reg = (modrm >> 3) & 0b111;
+            if reg & 0b100 != 0 {
+                return Err(cursor.invalid(opcode));
+            }
+
+            let sreg = Operand::Register(SEGMENT_REGISTERS[reg as usize]);
+            let rm = decode_rm(&mut cursor, modrm, 1)?;
+            let operands = if d == 1 { [sreg, rm] } else { [rm, sreg] };
+            (opcode, operands.map(Some), 1)
+        }
+        Opcode::Je
+        | Opcode::Jl
+        | Opcode::Jle
+        | Opcode::Jb
+        | Opcode::Jbe
+        | Opcode::Jp
+        | Opcode::Jo
+        | Opcode::Js
+        | Opcode::Jne
+        | Opcode::Jnl
+        | Opcode::Jg
+        | Opcode::Jnb
+        | Opcode::Ja
+        | Opcode::Jnp
+        | Opcode::Jno
+        | Opcode::Jns
+        | Opcode::Loop
+        | Opcode::Loopz
+        | Opcode::Loopnz
+        | Opcode::Jcxz => {
+            let displacement = cursor.next()? as i8 as i16;
+            (opcode, [Some(Operand::Relative(displacement)), None], 0)
+        }
+        Opcode::Call | Opcode::Jmp => {
+            let displacement = cursor.next_u16()? as i16;
+            (opcode, [Some(Operand::Relative(displacement)), None], 1)
+        }
+        Opcode::JmpShort => {
+            let displacement = cursor.next()? as i8 as i16;
+            (opcode, [Some(Operand::Relative(displacement)), None], 0)
+        }
+        Opcode::CallFar | Opcode::JmpFar => {
+            let offset = cursor.next_u16()?;
+            let segment = cursor.next_u16()?;
+            (opcode, [Some(Operand::Far(segment, offset)), None], 1)
+        }
+        Opcode::Ret | Opcode::Retf => {
+            // The low bit clear means an immediate count of bytes to release from the stack
+            let release = match b0 & 0b1 {
+                0 => Some(Operand::Immediate(cursor.next_u16()? as i16)),
+                _ => None,
+            };
+            (opcode, [release, None], 1)
+        }
+        Opcode::Int => {
+            // Interrupt types are unsigned, unlike sign-extended immediate data
+            let vector = cursor.next()? as i16;
+            (opcode, [Some(Operand::Immediate(vector)), None], 0)
+        }
+        Opcode::In | Opcode::InDx | Opcode::Out | Opcode::OutDx => {
+            let w = b0 & 0b1;
+            let accumulator = Operand::Register(register(0b000, w));
+            let port = match opcode {
+                // Fixed port numbers are unsigned bytes
+                Opcode::In | Opcode::Out => Operand::Immediate(cursor.next()? as i16),
+                _ => Operand::Register(Register::DX),
+            };
+            let operands = match opcode {
+                Opcode::In | Opcode::InDx => [accumulator, port],
+                _ => [port, accumulator],
+            };
+            (opcode, operands.map(Some), w)
+        }
+        Opcode::Int3
+        | Opcode::Into
+        | Opcode::Iret
+        | Opcode::Xlat
+        | Opcode::Lahf
+        | Opcode::Sahf
+        | Opcode::Cbw
+        | Opcode::Daa
+        | Opcode::Das
+        | Opcode::Aaa
+        | Opcode::Aas
+        | Opcode::Clc
+        | Opcode::Stc
+        | Opcode::Cmc
+        | Opcode::Cld
+        | Opcode::Std
+        | Opcode::Cli
+        | Opcode::Sti
+        | Opcode::Hlt
+        | Opcode::Wait => (opcode, [None, None], 0),
+        Opcode::Esc => {
+            // The 6-bit external opcode is split between the low bits of the first
+            // byte and the reg field; the r/m operand is for the coprocessor to use,
+            // which reads a register form as one of its own stack registers
+            let modrm = cursor.next()?;
+            let code = ((b0 & 0b111) << 3) | ((modrm >> 3) & 0b111);
+            let operand = match modrm >> 6 {
+                0b11 => Operand::St(modrm & 0b111),
+                _ => decode_rm(&mut cursor, modrm, 1)?,
+            };
+            (
+                opcode,
+                [Some(Operand::Immediate(code as i16)), Some(operand)],
+                1,
+            )
+        }
+        Opcode::Aam | Opcode::Aad => {
+            // The second byte is the number base; plain `aam` and `aad` encode 10
+            let base = cursor.next()?;
+            let operand = (base != 10).then_some(Operand::Immediate(base as i16));
+            (opcode, [operand, None], 0)
+        }
+        Opcode::Pushf | Opcode::Popf | Opcode::Cwd => (opcode, [None, None], 1),
+        // XCHG AX, AX is the canonical NOP
+        Opcode::XchgAR if b0 & 0b111 == 0b000 => (Opcode::Nop, [None, None], 0),
+        Opcode::XchgAR => {
+            let accumulator = Operand::Register(Register::AX);
+            let reg = Operand::Register(register(b0, 1));
+            (opcode, [Some(accumulator), Some(reg)], 1)
+        }
+        Opcode::Lea | Opcode::Lds | Opcode::Les => {
+            let modrm = cursor.next()?;
+            // Only a memory operand has an address to load
+            if modrm >> 6 == 0b11 {
+                return Err(cursor.invalid(opcode));
+            }
+
+            let reg = Operand::Register(register(modrm >> 3, 1));
+            let address = decode_rm(&mut cursor, modrm, 1)?;
+            (opcode, [Some(reg), Some(address)], 1)
+        }
+        Opcode::PushR | Opcode::PopR | Opcode::IncR | Opcode::DecR => {
+            let reg = Operand::Register(register(b0, 1));
+            (opcode, [Some(reg), None], 1)
+        }
+        Opcode::PushSr | Opcode::PopSr => {
+            let sreg = Operand::Register(SEGMENT_REGISTERS[((b0 >> 3) & 0b11) as usize]);
+            (opcode, [Some(sreg), None], 1)
+        }
+        Opcode::IncRm => {
+            let w = b0 & 0b1;
+            let modrm = cursor.next()?;
+            let opcode = match ((modrm >> 3) & 0b111, w) {
+                (0b000, _) => Opcode::IncRm,
+                (0b001, _) => Opcode::DecRm,
+                (0b010, 1) => Opcode::CallRm,
+                (0b011, 1) if modrm >> 6 != 0b11 => Opcode::CallFarRm,
+                (0b100, 1) => Opcode::JmpRm,
+                (0b101, 1) if modrm >> 6 != 0b11 => Opcode::JmpFarRm,
+                (0b110, 1) => Opcode::PushRm,
+                _ => return Err(cursor.invalid(opcode)),
+            };
+
+            let operand = decode_rm(&mut cursor, modrm, w)?;
+            (opcode, [Some(operand), None], w)
+        }
+        Opcode::PopRm => {
+            let modrm = cursor.next()?;
+            if (modrm >> 3) & 0b111 != 0b000 {
+                return Err(cursor.invalid(opcode));
+            }
+
+            let operand = decode_rm(&mut cursor, modrm, 1)?;
+            (opcode, [Some(operand), None], 1)
+        }
+        // Group members and prefixes are never produced by the trie directly
+        Opcode::SubIRm
+        | Opcode::CmpIRm
+        | Opcode::AndIRm
+        | Opcode::OrIRm
+        | Opcode::XorIRm
+        | Opcode::Not
+        | Opcode::AdcIRm
+        | Opcode::SbbIRm
+        | Opcode::DecRm
+        | Opcode::Neg
+        | Opcode::Mul
+        | Opcode::Imul
+        | Opcode::Div
+        | Opcode::Idiv
+        | Opcode::Ror
+        | Opcode::Rcl
+        | Opcode::Rcr
+        | Opcode::Shl
+        | Opcode::Shr
+        | Opcode::Sar
+        | Opcode::PushRm
+        | Opcode::CallRm
+        | Opcode::CallFarRm
+        | Opcode::JmpRm
+        | Opcode::JmpFarRm
+        | Opcode::Segment
+        | Opcode::Repeat
+        | Opcode::Lock
+        | Opcode::Nop => {
+            return Err(cursor.unknown());
+        }
+    };
+
+    Ok(Instruction {
+        opcode,
+        operands,
+        w,
+        offset,
+        length: cursor.pos - offset,
+        prefixes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_past_the_end_is_unexpected_end() {
+        for offset in [1, 3] {
+            match decode(&[0x90], offset) {
+                Err(DecodeError::UnexpectedEnd { offset: at, bytes }) => {
+                    assert_eq!(at, offset);
+                    assert!(bytes.is_empty());
+                }
+                other => panic!("expected UnexpectedEnd, got {other:?}"),
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_instruction_keeps_its_bytes() {
+        match decode(&[0x90, 0xB8, 0x34], 1) {
+            Err(DecodeError::UnexpectedEnd { offset, bytes }) => {
+                assert_eq!(offset, 1);
+                assert_eq!(bytes, [0xB8, 0x34]);
+            }
+            other => panic!("expected UnexpectedEnd, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn esc_register_form_names_a_coprocessor_register() {
+        let instruction = decode(&[0xD8, 0xC1], 0).unwrap();
+        assert_eq!(instruction.opcode, Opcode::Esc);
+        assert_eq!(
+            instruction.operands,
+            [Some(Operand::Immediate(0)), Some(Operand::St(1))]
+        );
+
+        let instruction = decode(&[0xDF, 0x47, 0x10], 0).unwrap();
+        assert_eq!(instruction.length, 3);
+        assert_eq!(instruction.dest(), Some(Operand::Immediate(0o70)));
+        assert!(matches!(instruction.source(), Some(Operand::Memory(_))));
+    }
+}
@@ -0,0 +1,85 @@
+// Diagnostics for the command-line tool: logging and the dumps it writes. None of
+// this is part of the library API.
+
+use cpu_parser::registers::REGISTERS;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,   // Nothing at all
+    Normal,  // Errors and requested reports
+    Verbose, // Plus per-instruction bytes and IP movement
+    Debug,   // Plus bit dumps and register dumps after every instruction
+}
+
+// Diagnostics sink, kept apart from stdout so the disassembly stays reassemblable
+pub struct Logger {
+    pub verbosity: Verbosity,
+    sink: Box<dyn Write>,
+}
+
+impl Logger {
+    pub fn stderr(verbosity: Verbosity) -> Self {
+        Self {
+            verbosity,
+            sink: Box::new(io::stderr()),
+        }
+    }
+
+    pub fn file<P: AsRef<Path>>(path: P, verbosity: Verbosity) -> io::Result<Self> {
+        Ok(Self {
+            verbosity,
+            sink: Box::new(io::BufWriter::new(File::create(path)?)),
+        })
+    }
+
+    pub fn enabled(&self, level: Verbosity) -> bool {
+        self.verbosity >= level && level > Verbosity::Quiet
+    }
+
+    pub fn log(&mut self, level: Verbosity, args: std::fmt::Arguments) {
+        if self.enabled(level) {
+            // A broken diagnostics sink must never abort the run
+            let _ = writeln!(self.sink, "{}", args);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let _ = self.sink.flush();
+    }
+}
+
+pub fn format_bits(bytes: &[u8]) -> String {
+    format!(
+        "[{}]",
+        bytes
+            .iter()
+            .map(|b| format!("{:08b}", b))
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+pub fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    std::fs::read(path).map_err(|e| format!("Failed to read file '{}': {}", path, e))
+}
+
+// One line per 16-bit register; a trailing odd byte has no register and is left out
+pub fn format_memory_16bit(mem: &[u8]) -> String {
+    mem.chunks_exact(2)
+        .enumerate()
+        .map(|(i, chunk)| format!("; R{i}: {:08b} {:08b}", chunk[1], chunk[0]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Labels each 16-bit register with its name; bytes past the last register are left out
+pub fn format_memory_hex(mem: &[u8]) -> String {
+    mem.chunks_exact(2)
+        .zip(REGISTERS[1])
+        .map(|(chunk, reg)| format!("; {}: {:02x} {:02x}", reg, chunk[1], chunk[0]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,251 @@
+use crate::decoder::decode;
+use crate::error::DecodeError;
+use crate::formatter::Labels;
+use crate::instruction::Instruction;
+use crate::opcodes::Opcode;
+use std::collections::BTreeMap;
+
+// One line of disassembly output
+#[derive(Clone, Debug, PartialEq)]
+pub enum Line {
+    Code(Instruction),
+    Data(u8), // A byte that is not decoded as code, kept as `db`
+}
+
+impl Line {
+    pub fn length(&self) -> usize {
+        match self {
+            Line::Code(instruction) => instruction.length,
+            Line::Data(_) => 1,
+        }
+    }
+}
+
+// What to do with bytes that do not decode
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Recovery {
+    Stop, // End the disassembly in front of the bad bytes
+    Data, // Keep the first byte as `db` and resume at the next one
+    Drop, // Leave out everything the decoder read and resume after it
+}
+
+// Disassembled lines keyed by the offset of their first byte. Nothing here depends
+// on simulated state, so every instruction appears exactly once.
+#[derive(Clone, Debug, Default)]
+pub struct Disassembly {
+    pub lines: BTreeMap<usize, Line>,
+    pub end: usize,                 // Offset just past the last line
+    pub error: Option<DecodeError>, // Why the disassembly stopped early, if it did
+}
+
+impl Disassembly {
+    // Names every branch target that starts a line, or the end of the output, in
+    // address order. Other targets have nowhere to put a label.
+    pub fn labels(&self) -> Labels {
+        let mut targets: Vec<usize> = self
+            .lines
+            .values()
+            .filter_map(|line| match line {
+                Line::Code(instruction) => instruction.branch_target(),
+                Line::Data(_) => None,
+            })
+            .filter(|target| *target == self.end || self.lines.contains_key(target))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+            .into_iter()
+            .enumerate()
+            .map(|(i, target)| (target, format!("label_{}", i)))
+            .collect()
+    }
+}
+
+// Decodes from `start` to the end of `bytes`, each instruction starting where the
+// previous one ended. `limit` caps the number of lines and `recover` is asked what
+// to do whenever decoding fails.
+pub fn linear_sweep(
+    bytes: &[u8],
+    start: usize,
+    limit: Option<u64>,
+    mut recover: impl FnMut(&DecodeError) -> Recovery,
+) -> Disassembly {
+    let mut disassembly = Disassembly::default();
+    let mut offset = start;
+
+    while offset < bytes.len() && limit.is_none_or(|max| (disassembly.lines.len() as u64) < max) {
+        match decode(bytes, offset) {
+            Ok(instruction) => {
+                disassembly.lines.insert(offset, Line::Code(instruction));
+                offset += instruction.length;
+            }
+            Err(e) => match recover(&e) {
+                Recovery::Stop => {
+                    disassembly.error = Some(e);
+                    break;
+                }
+                Recovery::Data => {
+                    disassembly.lines.insert(offset, Line::Data(bytes[offset]));
+                    offset += 1;
+                }
+                Recovery::Drop => offset += e.bytes().len().max(1),
+            },
+        }
+    }
+
+    disassembly.end = offset;
+    disassembly
+}
+
+// Offsets execution can reach from `instruction`, as far as the bytes alone tell:
+// the branch target of a relative jump, call or loop, and the next instruction
+// unless control never falls through
+fn successors(instruction: &Instruction) -> impl Iterator<Item = usize> {
+    let falls_through = !matches!(
+        instruction.opcode,
+        Opcode::Jmp
+            | Opcode::JmpShort
+            | Opcode::JmpRm
+            | Opcode::JmpFar
+            | Opcode::JmpFarRm
+            | Opcode::Ret
+            | Opcode::Retf
+            | Opcode::Iret
+            | Opcode::Hlt
+    );
+    let next = instruction.offset + instruction.length;
+    instruction
+        .branch_target()
+        .into_iter()
+        .chain(falls_through.then_some(next))
+}
+
+// Follows fall-through, jumps, calls and returns from `entry`, decoding only bytes
+// that execution can reach, including any before `entry`. Every other byte of the
+// file, including bytes that fail to decode, is kept as `db`, so the output still
+// assembles to the same bytes. `limit` caps the number of instructions decoded.
+pub fn recursive_descent(bytes: &[u8], entry: usize, limit: Option<u64>) -> Disassembly {
+    let mut disassembly = Disassembly::default();
+    let mut pending = vec![entry];
+    let mut decoded = 0;
+
+    while let Some(offset) = pending.pop() {
+        if offset >= bytes.len() || limit.is_some_and(|max| decoded >= max) {
+            continue;
+        }
+        // Already decoded, or the middle of an instruction that was
+        let covered = disassembly
+            .lines
+            .range(..=offset)
+            .next_back()
+            .is_some_and(|(start, line)| start + line.length() > offset);
+        if covered {
+            continue;
+        }
+
+        let Ok(instruction) = decode(bytes, offset) else {
+            continue;
+        };
+        // Code that would overlap an instruction decoded from another path stays data
+        let end = offset + instruction.length;
+        if disassembly.lines.range(offset..end).next().is_some() {
+            continue;
+        }
+
+        decoded += 1;
+        disassembly.lines.insert(offset, Line::Code(instruction));
+        pending.extend(successors(&instruction));
+    }
+
+    let mut offset = 0;
+    while offset < bytes.len() {
+        match disassembly.lines.get(&offset) {
+            Some(line) => offset += line.length(),
+            None => {
+                disassembly.lines.insert(offset, Line::Data(bytes[offset]));
+                offset += 1;
+            }
+        }
+    }
+
+    disassembly.end = offset;
+    disassembly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Offsets of the lines, with each line as `c` for code or `d` for data
+    fn layout(disassembly: &Disassembly) -> Vec<(usize, char)> {
+        disassembly
+            .lines
+            .iter()
+            .map(|(&offset, line)| match line {
+                Line::Code(_) => (offset, 'c'),
+                Line::Data(_) => (offset, 'd'),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bytes_before_the_entry_are_kept() {
+        // inc cx / inc dx / entry: mov cx, 3 / loop $ / jmp 0 / hlt
+        let bytes = [0x41, 0x42, 0xB9, 0x03, 0x00, 0xE2, 0xFE, 0xEB, 0xF7, 0xF4];
+        let disassembly = recursive_descent(&bytes, 2, None);
+        // The jump back makes the first two bytes code; nothing reaches the HLT
+        assert_eq!(
+            layout(&disassembly),
+            [(0, 'c'), (1, 'c'), (2, 'c'), (5, 'c'), (7, 'c'), (9, 'd')]
+        );
+        assert_eq!(disassembly.end, bytes.len());
+        assert_eq!(
+            disassembly.labels(),
+            Labels::from([(0, "label_0".into()), (5, "label_1".into())])
+        );
+
+        // Unreached bytes in front of the entry become data
+        let disassembly = recursive_descent(&[0xFF, 0xFF, 0xB0, 0x01, 0xF4], 2, None);
+        assert_eq!(
+            layout(&disassembly),
+            [(0, 'd'), (1, 'd'), (2, 'c'), (4, 'c')]
+        );
+    }
+
+    #[test]
+    fn target_inside_an_instruction_gets_no_label() {
+        // je 3 / mov ax, 0x9090 / hlt: the fall-through is decoded first and the
+        // branch lands in the middle of the MOV
+        let bytes = [0x74, 0x01, 0xB8, 0x90, 0x90, 0xF4];
+        let disassembly = recursive_descent(&bytes, 0, None);
+        assert_eq!(layout(&disassembly), [(0, 'c'), (2, 'c'), (5, 'c')]);
+        assert!(disassembly.labels().is_empty());
+    }
+
+    #[test]
+    fn code_overlapping_a_decoded_instruction_stays_data() {
+        // jmp 4 / (b8 00) / 4: nop / jmp 2. Decoding at 2 would read b8 00 90 as a
+        // MOV running into the NOP, so those bytes stay data but keep their label
+        let bytes = [0xEB, 0x02, 0xB8, 0x00, 0x90, 0xEB, 0xFB];
+        let disassembly = recursive_descent(&bytes, 0, None);
+        assert_eq!(
+            layout(&disassembly),
+            [(0, 'c'), (2, 'd'), (3, 'd'), (4, 'c'), (5, 'c')]
+        );
+        assert_eq!(
+            disassembly.labels(),
+            Labels::from([(2, "label_0".into()), (4, "label_1".into())])
+        );
+    }
+
+    #[test]
+    fn undecodable_bytes_become_data() {
+        // je 4 / ff ff / 4: hlt, where the fall-through reaches the invalid FF /7
+        let disassembly = recursive_descent(&[0x74, 0x02, 0xFF, 0xFF, 0xF4], 0, None);
+        assert_eq!(
+            layout(&disassembly),
+            [(0, 'c'), (2, 'd'), (3, 'd'), (4, 'c')]
+        );
+        assert!(disassembly.error.is_none());
+    }
+}
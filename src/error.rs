@@ -0,0 +1,126 @@
+use crate::instruction::Instruction;
+use crate::opcodes::Opcode;
+use crate::utility::format_hex;
+use std::fmt;
+
+// Every variant carries the offset of the instruction's first byte (including
+// prefixes) and the bytes that had been read when decoding failed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    UnknownOpcode {
+        offset: usize,
+        bytes: Vec<u8>,
+    },
+    InvalidModRm {
+        offset: usize,
+        bytes: Vec<u8>,
+        opcode: Opcode,
+    },
+    UnexpectedEnd {
+        offset: usize,
+        bytes: Vec<u8>,
+    },
+}
+
+impl DecodeError {
+    pub fn offset(&self) -> usize {
+        match self {
+            DecodeError::UnknownOpcode { offset, .. }
+            | DecodeError::InvalidModRm { offset, .. }
+            | DecodeError::UnexpectedEnd { offset, .. } => *offset,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            DecodeError::UnknownOpcode { bytes, .. }
+            | DecodeError::InvalidModRm { bytes, .. }
+            | DecodeError::UnexpectedEnd { bytes, .. } => bytes,
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownOpcode { offset, bytes } => {
+                write!(f, "Unknown opcode at {}: [{}]", offset, format_hex(bytes))
+            }
+            DecodeError::InvalidModRm {
+                offset,
+                bytes,
+                opcode,
+            } => write!(
+                f,
+                "Invalid mod/reg/rm byte for {:?} at {}: [{}]",
+                opcode,
+                offset,
+                format_hex(bytes)
+            ),
+            DecodeError::UnexpectedEnd { offset, bytes } => write!(
+                f,
+                "Unexpected end of file while reading instruction at {}: [{}]",
+                offset,
+                format_hex(bytes)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// The offending bytes are the `instruction.length` bytes at `instruction.offset`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecError {
+    Unsupported {
+        instruction: Instruction,
+    },
+    InvalidOperand {
+        instruction: Instruction,
+    },
+    UnhandledInterrupt {
+        instruction: Instruction,
+        vector: u8,
+    },
+}
+
+impl ExecError {
+    pub fn instruction(&self) -> &Instruction {
+        match self {
+            ExecError::Unsupported { instruction }
+            | ExecError::InvalidOperand { instruction }
+            | ExecError::UnhandledInterrupt { instruction, .. } => instruction,
+        }
+    }
+
+    pub fn offset(&self) -> usize {
+        self.instruction().offset
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::Unsupported { instruction } => write!(
+                f,
+                "Cannot execute {:?} at {}: {}",
+                instruction.opcode, instruction.offset, instruction
+            ),
+            ExecError::InvalidOperand { instruction } => write!(
+                f,
+                "Invalid operands for {:?} at {}: {}",
+                instruction.opcode, instruction.offset, instruction
+            ),
+            ExecError::UnhandledInterrupt {
+                instruction,
+                vector,
+            } => write!(
+                f,
+                "No handler for interrupt {} raised by {:?} at {}: {}",
+                vector, instruction.opcode, instruction.offset, instruction
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExecError {}
@@ -0,0 +1,166 @@
+use crate::instruction::{Instruction, MemoryOperand, Operand, Repeat};
+use crate::opcodes::Opcode;
+use crate::registers::Register;
+use std::collections::BTreeMap;
+use std::fmt;
+
+fn with_sign(n: i16) -> String {
+    if n >= 0 {
+        format!("+ {}", n)
+    } else {
+        format!("- {}", -(n as i32))
+    }
+}
+
+fn format_memory(memory: &MemoryOperand, segment: Option<Register>) -> String {
+    let prefix = segment.map(|s| format!("{}:", s)).unwrap_or_default();
+    match (memory.eac, memory.displacement) {
+        (Some(eac), Some(displacement)) => {
+            format!("{}[{} {}]", prefix, eac, with_sign(displacement))
+        }
+        (Some(eac), None) => format!("{}[{}]", prefix, eac),
+        (None, displacement) => format!("{}[{}]", prefix, displacement.unwrap_or(0) as u16),
+    }
+}
+
+// A relative operand prints as the bare displacement here; only the instruction
+// knows its own length, so `Instruction` writes those relative to `$` instead
+pub fn format_operand(operand: &Operand, segment: Option<Register>) -> String {
+    match operand {
+        Operand::Register(reg) => reg.to_string(),
+        Operand::Memory(memory) => format_memory(memory, segment),
+        Operand::Immediate(value) => value.to_string(),
+        Operand::Relative(displacement) => displacement.to_string(),
+        Operand::Far(segment, offset) => format!("{}:{}", segment, offset),
+        Operand::St(index) => format!("st{}", index),
+    }
+}
+
+// Names for branch targets, keyed by the offset of the instruction they label
+pub type Labels = BTreeMap<usize, String>;
+
+// `$` is the start of the current instruction, so this reassembles at any address
+fn format_relative(instruction: &Instruction, displacement: i16) -> String {
+    let distance = instruction.length as i32 + displacement as i32;
+    if distance >= 0 {
+        format!("$+{}", distance)
+    } else {
+        format!("$-{}", -distance)
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, None)
+    }
+}
+
+impl Instruction {
+    // nasm syntax with the branch target written as its label, or relative to `$`
+    // when the target has none
+    pub fn format_with_labels(&self, labels: &Labels) -> String {
+        let mut text = String::new();
+        // Writing into a String cannot fail
+        let _ = self.write(&mut text, Some(labels));
+        text
+    }
+
+    fn write(&self, f: &mut impl fmt::Write, labels: Option<&Labels>) -> fmt::Result {
+        if self.prefixes.lock {
+            write!(f, "lock ")?;
+        }
+        if let Some(repeat) = self.prefixes.repeat {
+            let prefix = match repeat {
+                Repeat::Rep if matches!(self.opcode, Opcode::Cmps | Opcode::Scas) => "repe",
+                Repeat::Rep => "rep",
+                Repeat::Repne => "repne",
+            };
+            write!(f, "{} ", prefix)?;
+        }
+        // Without a memory operand to attach it to, a segment override becomes a prefix
+        let has_memory = self
+            .operands
+            .iter()
+            .any(|operand| matches!(operand, Some(Operand::Memory(_))));
+        if let (Some(segment), false) = (self.prefixes.segment, has_memory) {
+            write!(f, "{} ", segment)?;
+        }
+
+        write!(f, "{}", self.opcode)?;
+        if self.opcode.is_string() {
+            write!(f, "{}", if self.w == 1 { "w" } else { "b" })?;
+        }
+
+        // Without a register operand nasm cannot infer the size of a memory access.
+        // A shift count in CL says nothing about the width of the shifted operand.
+        let is_shift = matches!(
+            self.opcode,
+            Opcode::Rol
+                | Opcode::Ror
+                | Opcode::Rcl
+                | Opcode::Rcr
+                | Opcode::Shl
+                | Opcode::Shr
+                | Opcode::Sar
+        );
+        // ESC hands its operand to the coprocessor, which decides the size itself
+        let needs_size = self.opcode != Opcode::Esc
+            && (is_shift
+                || !self
+                    .operands
+                    .iter()
+                    .any(|operand| matches!(operand, Some(Operand::Register(_)))));
+
+        for (i, operand) in self.operands.iter().flatten().enumerate() {
+            write!(f, "{}", if i == 0 { " " } else { ", " })?;
+            if matches!(self.opcode, Opcode::CallFarRm | Opcode::JmpFarRm) {
+                write!(f, "far ")?;
+            } else if self.opcode == Opcode::Jmp {
+                // Keep nasm from shortening E9 to EB when the target is close
+                write!(f, "near ")?;
+            } else if needs_size && matches!(operand, Operand::Memory(_)) {
+                write!(f, "{} ", if self.w == 1 { "word" } else { "byte" })?;
+            }
+
+            let label = labels
+                .zip(self.branch_target())
+                .and_then(|(labels, target)| labels.get(&target));
+            match (operand, label) {
+                (Operand::Relative(_), Some(label)) => write!(f, "{}", label)?,
+                (Operand::Relative(displacement), None) => {
+                    write!(f, "{}", format_relative(self, *displacement))?
+                }
+                _ => write!(f, "{}", format_operand(operand, self.prefixes.segment))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::decode;
+
+    #[test]
+    fn relative_targets_without_labels_are_relative_to_dollar() {
+        // jne -3 from the end of a 2-byte instruction lands 1 byte before it
+        assert_eq!(decode(&[0x75, 0xFD], 0).unwrap().to_string(), "jne $-1");
+        assert_eq!(decode(&[0xEB, 0x02], 0).unwrap().to_string(), "jmp $+4");
+        assert_eq!(
+            decode(&[0xE8, 0x03, 0x00], 0).unwrap().to_string(),
+            "call $+6"
+        );
+        assert_eq!(decode(&[0xE2, 0xFE], 0).unwrap().to_string(), "loop $+0");
+    }
+
+    #[test]
+    fn labels_replace_relative_targets() {
+        let labels = Labels::from([(7, "label_0".to_string())]);
+        let jump = decode(&[0x90, 0x90, 0x90, 0x74, 0x02], 3).unwrap();
+        assert_eq!(jump.format_with_labels(&labels), "je label_0");
+        // A target without a label falls back to `$`
+        let jump = decode(&[0x74, 0x06], 0).unwrap();
+        assert_eq!(jump.format_with_labels(&labels), "je $+8");
+    }
+}
@@ -0,0 +1,76 @@
+use crate::opcodes::Opcode;
+use crate::registers::{EAC, Register};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MemoryOperand {
+    pub eac: Option<EAC>,          // None for a direct address
+    pub displacement: Option<i16>, // None when mod = 00 carries no displacement
+}
+
+impl MemoryOperand {
+    pub fn direct(address: u16) -> Self {
+        Self {
+            eac: None,
+            displacement: Some(address as i16),
+        }
+    }
+
+    pub fn default_segment(&self) -> Register {
+        self.eac
+            .map(|eac| eac.default_segment())
+            .unwrap_or(Register::DS)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    Memory(MemoryOperand),
+    Immediate(i16),
+    Relative(i16), // Jump displacement from the end of the instruction
+    Far(u16, u16), // Segment and offset of a direct intersegment CALL or JMP
+    St(u8),        // 8087 stack register ST(i), the register form of an ESC operand
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Repeat {
+    Rep,   // F3: REP, or REPE/REPZ for CMPS and SCAS
+    Repne, // F2: REPNE/REPNZ
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Prefixes {
+    pub segment: Option<Register>,
+    pub repeat: Option<Repeat>,
+    pub lock: bool,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub opcode: Opcode,
+    pub operands: [Option<Operand>; 2], // Destination first, like nasm
+    pub w: u8,                          // 0 = byte, 1 = word
+    pub offset: usize,                  // Where the first byte (including prefixes) was read
+    pub length: usize,                  // Encoded size in bytes, including prefixes
+    pub prefixes: Prefixes,
+}
+
+impl Instruction {
+    pub fn dest(&self) -> Option<Operand> {
+        self.operands[0]
+    }
+
+    pub fn source(&self) -> Option<Operand> {
+        self.operands[1]
+    }
+
+    // Where a relative jump, call or loop continues when taken
+    pub fn branch_target(&self) -> Option<usize> {
+        match self.dest() {
+            Some(Operand::Relative(displacement)) => {
+                (self.offset + self.length).checked_add_signed(displacement as isize)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,28 @@
+//! 8086 instruction decoder, nasm-compatible disassembler and simulator.
+//!
+//! `decoder::decode` turns bytes into an `Instruction` without side effects,
+//! its `Display` implementation prints nasm syntax, and `Simulator` executes it
+//! against a `RegisterFile` and a 1 MiB `Memory`. `disassembler` walks whole
+//! programs statically, without running them.
+
+pub mod decoder;
+pub mod disassembler;
+pub mod error;
+pub mod formatter;
+pub mod instruction;
+pub mod memory;
+pub mod opcodes;
+pub mod ports;
+pub mod registers;
+pub mod simulator;
+pub mod utility;
+
+pub use decoder::decode;
+pub use disassembler::{Disassembly, Line, Recovery, linear_sweep, recursive_descent};
+pub use error::{DecodeError, ExecError};
+pub use instruction::{Instruction, MemoryOperand, Operand, Prefixes, Repeat};
+pub use memory::Memory;
+pub use opcodes::Opcode;
+pub use ports::{OpenBus, PortBus};
+pub use registers::{EAC, Flag, Register, RegisterFile};
+pub use simulator::{InterruptHandler, Location, Simulator};
@@ -1,553 +1,524 @@
-use core::panic;
 use std::env;
+use std::io::Write;
+
+mod diagnostics;
+
+use cpu_parser::registers::{REGISTERS, SEGMENT_REGISTERS};
+use cpu_parser::utility::format_hex;
+use cpu_parser::{
+    Instruction, Line, Opcode, OpenBus, PortBus, Recovery, Register, RegisterFile, Simulator,
+    linear_sweep, recursive_descent,
+};
+use diagnostics::{
+    Logger, Verbosity, format_bits, format_memory_16bit, format_memory_hex, read_file,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Command {
+    Disasm, // Print the program as nasm source without executing it
+    Exec,   // Execute silently and report the final registers and flags
+    Trace,  // Execute and print every instruction with the registers it changed
+    Dump,   // Execute and dump simulated memory
+}
 
-mod opcodes;
-mod registers;
-mod utility;
-
-use opcodes::{OPCODE_TRIE, Opcode};
-use registers::{EACS, RegisterFile, retrieve_register};
-use utility::{DEBUG, debug_bytes, read_file};
-
-use crate::registers::Register;
-use crate::utility::{print_memory_16bit, print_memory_hex};
-
-macro_rules! debug {
-    ($($arg:tt)*) => {
-        if utility::DEBUG {
-            println!($($arg)*);
+impl Command {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "disasm" => Some(Command::Disasm),
+            "exec" => Some(Command::Exec),
+            "trace" => Some(Command::Trace),
+            "dump" => Some(Command::Dump),
+            _ => None,
         }
-    };
+    }
 }
 
-fn main() {
-    let mut args = env::args();
-
-    args.next();
-
-    let mut memory = RegisterFile::new();
-    print_memory_16bit(&memory.raw_memory());
-
-    let path = match args.next() {
-        Some(arg) => arg,
-        None => {
-            eprintln!(
-                "Usage: {} <path_to_file>",
-                args.next().unwrap_or_else(|| "program".to_string())
-            );
-            std::process::exit(1);
-        }
-    };
+// What the CLI does when an instruction cannot be decoded or executed
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum OnError {
+    Stop,     // Report the error and exit
+    Skip,     // Emit the offending byte as `db` and resume at the next byte
+    Continue, // Drop the bytes that failed to decode and carry on after them
+}
 
-    let file = match read_file(&path) {
-        Ok(content) => content,
-        Err(e) => {
-            eprintln!("Error reading file: {}", e);
-            std::process::exit(1);
+impl OnError {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "stop" => Some(OnError::Stop),
+            "skip" => Some(OnError::Skip),
+            "continue" => Some(OnError::Continue),
+            _ => None,
         }
-    };
-
-    println!("; File read successfully, size: {} bytes", file.len());
-
-    fn read_or_exit(file: &[u8], index: usize, context: &str) -> u8 {
-        *file.get(index).unwrap_or_else(|| {
-            eprintln!("Unexpected end of file while reading {}", context);
-            std::process::exit(1);
-        })
     }
+}
 
-    loop {
-        let ip = memory.get(Register::IP) as usize;
-        if ip >= file.len() {
-            break;
-        }
-
-        let b0 = read_or_exit(&file, ip, "opcode");
+struct Options {
+    command: Command,
+    path: String,
+    on_error: OnError,
+    verbosity: Verbosity,
+    trace: bool,
+    dump_regs: bool,
+    log: Option<String>,
+    start: u16,             // File offset of the first instruction
+    max_steps: Option<u64>, // Instructions to decode or execute before giving up
+    org: u16,               // Offset in CS the program is loaded at
+    from: u32,              // First physical address written by `dump`
+    length: u32,            // Number of bytes written by `dump`
+    output: Option<String>, // Raw memory image written by `dump` instead of a hexdump
+    console: Option<u16>,   // Port whose OUT bytes are copied to stdout
+    listing: bool,          // Append each instruction's address and bytes as a comment
+    recursive: bool,        // Disassemble only code reachable from --start
+}
 
-        let opcode = OPCODE_TRIE
-            .match_bits(b0)
-            .map(|(opcode, _)| opcode)
-            .unwrap_or_else(|| {
-                eprintln!("Unknown opcode: {:08b}", b0);
-                std::process::exit(1);
-            });
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} [disasm|exec|trace|dump] [options] <path_to_file>
+
+Commands:
+  disasm                         Print the program as nasm source (default)
+  exec                           Execute silently and print the final registers and flags
+  trace                          Execute and print every instruction with the registers it changed
+  dump                           Execute and print a hexdump of simulated memory
+
+Options:
+  --start <offset>               File offset of the first instruction
+  --max-steps <n>                Stop after decoding or executing <n> instructions
+  --org <offset>                 Offset in CS the program is loaded at
+  --from <address>               First physical address to dump
+  --length <n>                   Number of bytes to dump (default 65536)
+  --output <file>                Write the dumped memory to <file> as raw bytes
+  --console <port>               Copy bytes written to I/O port <port> to stdout
+  --listing                      Annotate disasm and trace lines with addresses and bytes
+  --recursive                    Follow control flow from --start and emit unreached bytes as db
+  --on-error stop|skip|continue  What to do with undecodable or unexecutable bytes (not with
+                                 disasm --recursive, which always keeps them as db)
+  --quiet                        Suppress all diagnostics
+  -v, -vv                        Log instruction bytes and IP movement, then bit and register dumps
+  --trace                        Log every executed instruction with the registers it changed
+  --dump-regs                    Log the final registers and flags
+  --log <file>                   Write diagnostics to <file> instead of stderr
+
+Numbers may be given in decimal or as 0x-prefixed hex.",
+        program
+    );
+    std::process::exit(1);
+}
 
-        match opcode {
-            Opcode::MovMA => {
-                let mut bytes = vec![b0];
+fn parse_number(value: Option<String>) -> Option<u64> {
+    let value = value?;
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
 
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "mov m-a addr_lo"));
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "mov m-a addr_hi"));
+fn parse_args() -> Options {
+    let mut args = env::args().peekable();
+    let program = args.next().unwrap_or_else(|| "program".to_string());
 
-                process_mov_ma(&bytes, bytes.len() as u8, &mut memory);
-            }
-            Opcode::MovAM => {
-                let mut bytes = vec![b0];
+    let command = match args.peek().and_then(|arg| Command::parse(arg)) {
+        Some(command) => {
+            args.next();
+            command
+        }
+        None => Command::Disasm,
+    };
 
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "mov a-m addr_lo"));
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "mov a-m addr_hi"));
+    let mut path = None;
+    let mut on_error_given = false;
+    let mut options = Options {
+        command,
+        path: String::new(),
+        on_error: OnError::Stop,
+        verbosity: Verbosity::Normal,
+        trace: false,
+        dump_regs: false,
+        log: None,
+        start: 0,
+        max_steps: None,
+        org: 0,
+        from: 0,
+        length: 0x10000,
+        output: None,
+        console: None,
+        listing: false,
+        recursive: false,
+    };
 
-                process_mov_am(&bytes, bytes.len() as u8, &mut memory);
+    while let Some(arg) = args.next() {
+        // Accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
             }
-            Opcode::MovIR => {
-                let w = (b0 >> 3) & 0b1;
-                let mut bytes = vec![b0];
-
-                for _ in 0..w + 1 {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "mov i-r data"));
-                }
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline_value.clone().or_else(|| args.next());
+        let mut number = |max: u64| {
+            parse_number(value())
+                .filter(|n| *n <= max)
+                .unwrap_or_else(|| usage(&program))
+        };
 
-                process_ir(&bytes, bytes.len() as u8, Opcode::MovIR, &mut memory);
+        match flag.as_str() {
+            "--start" => options.start = number(u16::MAX as u64) as u16,
+            "--max-steps" => options.max_steps = Some(number(u64::MAX)),
+            "--org" => options.org = number(u16::MAX as u64) as u16,
+            "--from" => options.from = number(0xFFFFF) as u32,
+            "--length" => options.length = number(0x100000) as u32,
+            "--output" => options.output = Some(value().unwrap_or_else(|| usage(&program))),
+            "--console" => options.console = Some(number(u16::MAX as u64) as u16),
+            "--listing" => options.listing = true,
+            "--recursive" => options.recursive = true,
+            "--on-error" => {
+                on_error_given = true;
+                options.on_error = value()
+                    .as_deref()
+                    .and_then(OnError::parse)
+                    .unwrap_or_else(|| usage(&program));
             }
-            Opcode::MovIRm => {
-                let w = b0 & 0b1;
-                let mut bytes = vec![b0];
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "mov i-rm b[1]"));
-
-                let mode = bytes[1] >> 6;
-                let displacement_registers = if mode == 0b11 { 0 } else { mode };
-
-                for _ in 0..displacement_registers {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "mov i-rm displ"));
-                }
+            "--log" => options.log = Some(value().unwrap_or_else(|| usage(&program))),
+            "--quiet" => options.verbosity = Verbosity::Quiet,
+            "-v" => options.verbosity = Verbosity::Verbose,
+            "-vv" => options.verbosity = Verbosity::Debug,
+            "--trace" => options.trace = true,
+            "--dump-regs" => options.dump_regs = true,
+            _ if path.is_none() && !flag.starts_with('-') => path = Some(arg),
+            _ => usage(&program),
+        }
+    }
 
-                for _ in 0..w + 1 {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "mov i-rm data"));
-                }
+    // Recursive descent turns every byte it cannot decode into data, so a policy
+    // for them would be silently ignored
+    if options.command == Command::Disasm && options.recursive && on_error_given {
+        eprintln!("--on-error cannot be combined with disasm --recursive");
+        std::process::exit(1);
+    }
 
-                process_irm(&bytes, bytes.len() as u8, Opcode::MovIRm, &mut memory);
-            }
-            Opcode::AddIRm | Opcode::SubIRm | Opcode::CmpIRm => {
-                let w = b0 & 0b1;
-                let s = (b0 >> 1) & 0b1;
-                let mut bytes = vec![b0];
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "add i-rm b[1]"));
-
-                let regormem = bytes[1] & 0b111;
-
-                let mode = bytes[1] >> 6;
-                let displacement_registers = if mode == 0b11 {
-                    0
-                } else {
-                    if regormem == 0b110 { 2 } else { mode }
-                };
+    options.path = path.unwrap_or_else(|| usage(&program));
+    options
+}
 
-                for _ in 0..displacement_registers {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "add i-rm displ"));
-                }
+fn all_registers() -> impl Iterator<Item = Register> {
+    REGISTERS[1]
+        .iter()
+        .chain(SEGMENT_REGISTERS.iter())
+        .copied()
+        .chain([Register::IP])
+}
 
-                for _ in 0..(w + 1 - s) {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "add i-rm data"));
-                }
+// Registers and flags that differ between two snapshots, e.g. " cx:0x0000->0x00c8 flags:->PZ"
+fn register_changes(before: &RegisterFile, after: &RegisterFile) -> String {
+    let mut changes = String::new();
+    for reg in all_registers() {
+        if before.get(reg) != after.get(reg) {
+            changes += &format!(" {}:{:#06x}->{:#06x}", reg, before.get(reg), after.get(reg));
+        }
+    }
+    if before.flags() != after.flags() {
+        changes += &format!(" flags:{}->{}", before.flags_string(), after.flags_string());
+    }
+    changes
+}
 
-                process_irm(&bytes, bytes.len() as u8, opcode, &mut memory);
-            }
-            Opcode::AddIA | Opcode::SubIA | Opcode::CmpIA => {
-                let w = b0 & 0b1;
-                let mut bytes = vec![b0];
+fn format_registers(registers: &RegisterFile) -> Vec<String> {
+    let mut lines = vec!["; Final registers:".to_string()];
+    for reg in all_registers() {
+        let value = registers.get(reg);
+        lines.push(format!(";     {}: {:#06x} ({})", reg, value, value));
+    }
+    lines.push(format!(";  flags: {}", registers.flags_string()));
+    lines
+}
 
-                for _ in 0..w + 1 {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "mov i-r data"));
-                }
+// Exits for OnError::Stop, otherwise hands the policy back to the caller
+fn on_error_exit(on_error: OnError, logger: &mut Logger) -> OnError {
+    if on_error == OnError::Stop {
+        logger.flush();
+        std::process::exit(1);
+    }
+    on_error
+}
 
-                process_ir(&bytes, bytes.len() as u8, opcode, &mut memory);
-            }
-            Opcode::SubRmR | Opcode::AddRmR | Opcode::CmpRmR | Opcode::MovRmR => {
-                let mut bytes = vec![b0];
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "sub rm-r b[1]"));
-
-                let mode = bytes[1] >> 6;
-                let displacement_registers = if mode == 0b11 {
-                    0
-                } else {
-                    // Special case for 16-bit displacement
-                    if mode == 0b00 && (bytes[1] & 0b111) == 0b110 {
-                        2
-                    } else {
-                        mode
-                    }
-                };
+// Pads `text` and appends the address and encoding as a comment, so the line still assembles
+fn listing_line(text: &str, address: &str, bytes: &[u8]) -> String {
+    format!("{:<32}; {}  {}", text, address, format_hex(bytes))
+}
 
-                for _ in 0..displacement_registers {
-                    bytes.push(read_or_exit(&file, ip + bytes.len(), "{} rm-r displ"));
-                }
+// nasm has no ESC mnemonic, so a coprocessor instruction is kept as its bytes
+// with the decoding as a comment
+fn coprocessor_line(instruction: &Instruction, bytes: &[u8]) -> Option<String> {
+    if instruction.opcode != Opcode::Esc {
+        return None;
+    }
+    let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:#04x}", byte)).collect();
+    Some(format!("db {} ; {}", bytes.join(", "), instruction))
+}
 
-                process_rmr(&bytes, bytes.len() as u8, opcode, &mut memory);
-            }
-            Opcode::Je
-            | Opcode::Jl
-            | Opcode::Jle
-            | Opcode::Jb
-            | Opcode::Jbe
-            | Opcode::Jp
-            | Opcode::Jo
-            | Opcode::Js
-            | Opcode::Jne
-            | Opcode::Jnl
-            | Opcode::Jg
-            | Opcode::Jnb
-            | Opcode::Ja
-            | Opcode::Jnp
-            | Opcode::Jno
-            | Opcode::Jns
-            | Opcode::Loop
-            | Opcode::Loopz
-            | Opcode::Loopnz
-            | Opcode::Jcxz => {
-                let mut bytes = vec![b0];
-
-                bytes.push(read_or_exit(&file, ip + bytes.len(), "jmp ip-inc8"));
-
-                process_jmp(&bytes, bytes.len() as u8, opcode, &mut memory);
+// Decodes the whole file first so that every branch target is known before
+// printing, then emits a `label_N:` line ahead of each targeted instruction
+fn disassemble(file: &[u8], options: &Options, logger: &mut Logger) {
+    let start = options.start as usize;
+    let disassembly = if options.recursive {
+        recursive_descent(file, start, options.max_steps)
+    } else {
+        linear_sweep(file, start, options.max_steps, |e| {
+            logger.log(Verbosity::Normal, format_args!("; error: {}", e));
+            match options.on_error {
+                OnError::Stop => Recovery::Stop,
+                OnError::Skip => Recovery::Data,
+                OnError::Continue => Recovery::Drop,
             }
+        })
+    };
+    let labels = disassembly.labels();
+
+    for (&start, line) in &disassembly.lines {
+        let bytes = &file[start..start + line.length()];
+        logger.log(
+            Verbosity::Verbose,
+            format_args!("; {:#06x}: {}", start, format_hex(bytes)),
+        );
+        logger.log(
+            Verbosity::Debug,
+            format_args!("; Processing bytes: {}", format_bits(bytes)),
+        );
+
+        if let Some(label) = labels.get(&start) {
+            println!("{}:", label);
+        }
+        let text = match line {
+            Line::Code(instruction) => coprocessor_line(instruction, bytes)
+                .unwrap_or_else(|| instruction.format_with_labels(&labels)),
+            Line::Data(byte) => format!("db {:#04x}", byte),
         };
-        println!();
+        if options.listing {
+            // Addresses are where the bytes land in CS once loaded at --org
+            let address = format!("{:04x}", options.org as usize + start);
+            println!("{}", listing_line(&text, &address, bytes));
+        } else {
+            println!("{}", text);
+        }
+    }
+    if let Some(label) = labels.get(&disassembly.end) {
+        println!("{}:", label);
     }
-}
 
-fn process_irm(bytes: &[u8], size: u8, op: Opcode, memory: &mut RegisterFile) {
-    memory.move_ip_by_n(bytes.len());
-    debug!("; Processing irm:");
-    debug_bytes(bytes);
-    assert!(
-        size >= 3 && size <= 6,
-        "Invalid size for {} irm: {}",
-        op,
-        size
-    );
+    if disassembly.error.is_some() {
+        on_error_exit(OnError::Stop, logger);
+    }
+}
 
-    let w = bytes[0] & 0b1;
-    let reg = (bytes[1] >> 3) & 0b111;
-    let mode = bytes[1] >> 6;
-    let regormem = bytes[1] & 0b111;
+// Prints the low byte of every OUT to one port; everything else is left floating
+struct Console {
+    port: u16,
+}
 
-    let is_arithmetic = op == Opcode::AddIRm || op == Opcode::SubIRm || op == Opcode::CmpIRm;
-    let s = if is_arithmetic {
-        (bytes[0] >> 1) & 0b1
-    } else {
-        0
-    };
+impl PortBus for Console {
+    fn read(&mut self, port: u16, w: u8) -> u16 {
+        OpenBus.read(port, w)
+    }
 
-    //println!("reg {:03b}, opcode: {}", reg, op);
-    let op = if op == Opcode::AddIRm {
-        match reg {
-            0b101 => Opcode::SubIRm,
-            0b000 => Opcode::AddIRm,
-            0b111 => Opcode::CmpIRm,
-            _ => panic!("Invalid reg for AddRmR: {}", regormem),
+    fn write(&mut self, port: u16, _w: u8, value: u16) {
+        if port == self.port {
+            let mut stdout = std::io::stdout();
+            let _ = stdout.write_all(&[value as u8]);
+            let _ = stdout.flush();
         }
-    } else {
-        op
-    };
-    debug!(
-        "; Processing {} with mode {:02b}, reg: {:03b}, regormem: {:03b}, w = {}, s = {}",
-        op, mode, reg, regormem, w, s
-    );
+    }
+}
 
-    match mode {
-        0b00 => {
-            // Immediate to memory
-            assert!(
-                size == 4 || size == 3 || size == 5,
-                "Invalid size for {} irm mode im to mem: {}",
-                op,
-                size
-            );
-            let value = if size == 3 {
-                let _b = bytes[2] as i8;
-                _b as i16
-            } else {
-                ((bytes[3] as i16) << 8) | (bytes[2] as i16)
-            };
-            let dest = if regormem == 0b110 {
-                &value.to_string()
-            } else {
-                &EACS[regormem as usize].to_string()
-            };
-            let value = if size == 3 {
-                let _b = bytes[2] as i8;
-                _b as i16
-            } else if size == 5 {
-                let _b = bytes[4] as i8;
-                _b as i16
-            } else {
-                ((bytes[3] as i16) << 8) | (bytes[2] as i16)
-            };
-            let size = if size + s == 3 { "byte" } else { "word" };
-            println!("{} [{}], {} {}", op, dest, size, value);
-        }
+fn run(file: &[u8], options: &Options, logger: &mut Logger) -> Simulator {
+    let mut simulator = Simulator::new();
+    if let Some(port) = options.console {
+        simulator.set_port_bus(Box::new(Console { port }));
+    }
+    simulator.load_program(file, options.org);
+    simulator
+        .registers
+        .set(Register::IP, options.org.wrapping_add(options.start));
+
+    if logger.enabled(Verbosity::Debug) {
+        let dump = format_memory_16bit(&simulator.registers.raw_memory());
+        logger.log(Verbosity::Debug, format_args!("{}", dump));
+    }
 
-        0b01 => {
-            // Immediate to memory with 8-bit displacement
-            assert!(
-                size == 4,
-                "Invalid size for {} irm mode im to mem + 8b: {}",
-                op,
-                size
-            );
-            let dest = EACS[regormem as usize];
-            let displacement = bytes[2] as i8;
-            let value = bytes[3] as i8;
-            println!(
-                "{} [{} {}], word {}",
-                op,
-                dest,
-                with_sign(displacement as i16),
-                value
+    // Execution ends at HLT or when CS:IP leaves the loaded image. The image is
+    // located from CS:org rather than from IP, which wraps when org + start does
+    let image_start = simulator
+        .registers
+        .physical_address(Register::CS, options.org);
+    let image = image_start..image_start + file.len() as u32;
+    let mut steps = 0;
+
+    while !simulator.halted() && image.contains(&simulator.instruction_address()) {
+        if options.max_steps.is_some_and(|max| steps >= max) {
+            logger.log(
+                Verbosity::Normal,
+                format_args!("; stopped after {} steps", steps),
             );
+            break;
         }
+        steps += 1;
+
+        let address = simulator.instruction_address();
+        let instruction = match simulator.fetch() {
+            Ok(instruction) => instruction,
+            Err(e) => {
+                logger.log(Verbosity::Normal, format_args!("; error: {}", e));
+                let skipped = match on_error_exit(options.on_error, logger) {
+                    OnError::Skip => 1,
+                    _ => e.bytes().len().max(1),
+                };
+                simulator.registers.move_ip_by_n(skipped);
+                continue;
+            }
+        };
 
-        0b10 => {
-            // Immediate to memory with 16-bit displacement
-            assert!(
-                size == 6 || size == 5,
-                "Invalid size for {} irm mode im to mem + 16b: {}",
-                op,
-                size
+        let bytes = simulator.memory.as_bytes()[address as usize..][..instruction.length].to_vec();
+        let cs_ip = format!(
+            "{:04x}:{:04x}",
+            simulator.registers.get(Register::CS),
+            simulator.registers.get(Register::IP)
+        );
+        if logger.enabled(Verbosity::Verbose) {
+            logger.log(
+                Verbosity::Verbose,
+                format_args!("; {:#07x}: {}", address, format_hex(&bytes)),
             );
-            let dest = EACS[regormem as usize];
-            let displacement = ((bytes[3] as i16) << 8) | (bytes[2] as i16);
-            let value = if is_arithmetic {
-                bytes[4] as i16
-            } else {
-                ((bytes[5] as i16) << 8) | (bytes[4] as i16)
-            };
-            println!(
-                "{} [{} {}], word {}",
-                op,
-                dest,
-                with_sign(displacement),
-                value
+            logger.log(
+                Verbosity::Debug,
+                format_args!("; Processing bytes: {}", format_bits(&bytes)),
             );
         }
 
-        0b11 => {
-            // Immediate to register
-            assert!(
-                size == 3 || size == 4,
-                "Invalid size for {} irm mode im to reg: {}",
-                op,
-                size
-            );
-            let dest = retrieve_register(regormem, w).expect("Failed to get destination register");
-
-            let value = if s == 1 && w == 1 {
-                bytes[2] as i8 as i16 // sign-extend 8-bit immediate
-            } else if w == 0 {
-                bytes[2] as i8 as i16 // also sign-extend for 8-bit ops
-            } else {
-                (bytes[3] as i16) << 8 | (bytes[2] as i16)
-            };
-
-            if is_arithmetic {
-                perform_arithmetic(op, dest, value, memory);
-            } else {
-                move_data(dest, value, memory);
-            }
-            println!("{} {}, {}", op, dest, value);
+        let before = simulator.registers.clone();
+        if let Err(e) = simulator.execute(&instruction) {
+            logger.log(Verbosity::Normal, format_args!("; error: {}", e));
+            on_error_exit(options.on_error, logger);
         }
 
-        _ => {
-            panic!("Unsupported mode: {:02b}", mode);
-        }
-    }
-}
-
-fn process_rmr(bytes: &[u8], size: u8, op: Opcode, memory: &mut RegisterFile) {
-    memory.move_ip_by_n(bytes.len());
-    debug!("; Processing rmr:");
-    debug_bytes(bytes);
-    assert!(
-        size >= 2 && size <= 4,
-        "Invalid size for {} rmr: {}",
-        op,
-        size
-    );
-
-    let d = (bytes[0] >> 1) & 0b1;
-    let w = bytes[0] & 0b1;
-
-    let mode = bytes[1] >> 6;
-    let reg = (bytes[1] >> 3) & 0b111;
-    let regormem = bytes[1] & 0b111;
-
-    let is_arithmetic = op == Opcode::AddRmR || op == Opcode::SubRmR || op == Opcode::CmpRmR;
-
-    match mode {
-        0b00 => {
-            let source = if regormem == 0b110 {
-                (((bytes[3] as i16) << 8) | (bytes[2] as i16)).to_string()
-            } else {
-                EACS[regormem as usize].to_string()
-            };
-            let dest = retrieve_register(reg, w).expect("Failed to get source register");
-            if d == 1 {
-                println!("{} {}, [{}]", op, dest, source);
-            } else {
-                println!("{} [{}], {}", op, source, dest);
-            }
+        // Vectors stored inside the image already fail as unhandled. One the program
+        // set up to point outside it would otherwise end the run as quietly as
+        // falling off the end of the program
+        let next = address + instruction.length as u32;
+        let target = simulator.instruction_address();
+        let raises = matches!(
+            instruction.opcode,
+            Opcode::Int | Opcode::Int3 | Opcode::Into
+        );
+        if raises && target != next && !image.contains(&target) {
+            logger.log(
+                Verbosity::Normal,
+                format_args!(
+                    "; error: {} at {} jumped to a handler outside the loaded image at {:04x}:{:04x}",
+                    instruction,
+                    instruction.offset,
+                    simulator.registers.get(Register::CS),
+                    simulator.registers.get(Register::IP)
+                ),
+            );
+            on_error_exit(options.on_error, logger);
         }
 
-        0b01 => {
-            assert!(size == 3);
-            let source = EACS[regormem as usize];
-            let dest = retrieve_register(reg, w).expect("Failed to get source register");
-            let _disp = bytes[2] as i8;
-            let displacement = _disp as i16;
-            if d == 1 {
-                println!("{} {}, [{} {}]", op, dest, source, with_sign(displacement));
-            } else {
-                println!("{} [{} {}], {}", op, source, with_sign(displacement), dest);
-            }
+        logger.log(
+            Verbosity::Verbose,
+            format_args!(
+                "; Moving IP from {} to {}",
+                before.get(Register::IP),
+                simulator.registers.get(Register::IP)
+            ),
+        );
+        if logger.enabled(Verbosity::Debug) {
+            let dump = format_memory_hex(&simulator.registers.raw_memory());
+            logger.log(Verbosity::Debug, format_args!("{}", dump));
+            logger.log(
+                Verbosity::Debug,
+                format_args!("; Flags: {:016b}", simulator.registers.flags()),
+            );
         }
 
-        0b10 => {
-            assert!(size == 4);
-            let source = EACS[regormem as usize];
-            let dest = retrieve_register(reg, w).expect("Failed to get source register");
-            let displacement = ((bytes[3] as i16) << 8) | (bytes[2] as i16);
-            if d == 1 {
-                println!("{} {}, [{} {}]", op, dest, source, with_sign(displacement));
-            } else {
-                println!("{} [{} {}], {}", op, source, with_sign(displacement), dest);
-            }
+        let changes = register_changes(&before, &simulator.registers);
+        let text =
+            coprocessor_line(&instruction, &bytes).unwrap_or_else(|| instruction.to_string());
+        if options.command == Command::Trace && options.listing {
+            let line = listing_line(&text, &cs_ip, &bytes);
+            println!("{} ;{}", line, changes);
+        } else if options.command == Command::Trace {
+            println!("{} ;{}", text, changes);
         }
-
-        0b11 => {
-            let (source, destination) = if d == 1 {
-                (
-                    retrieve_register(regormem, w).expect("Failed to get source register"),
-                    retrieve_register(reg, w).expect("Failed to get destination register"),
-                )
-            } else {
-                (
-                    retrieve_register(reg, w).expect("Failed to get source register"),
-                    retrieve_register(regormem, w).expect("Failed to get destination register"),
-                )
-            };
-            if is_arithmetic {
-                perform_arithmetic(op, destination, memory.get(source) as i16, memory);
-            } else {
-                move_data(destination, memory.get(source) as i16, memory);
-            }
-
-            println!("{} {}, {}", op, destination, source);
+        if options.trace {
+            logger.log(
+                Verbosity::Normal,
+                format_args!("{} ;{}", instruction, changes),
+            );
         }
-
-        _ => panic!("Invalid mode: {:02b}", mode),
     }
-}
 
-fn process_mov_ma(bytes: &[u8], size: u8, memory: &mut RegisterFile) {
-    memory.move_ip_by_n(bytes.len());
-    debug!("; Processing mov ma:");
-    debug_bytes(bytes);
-    assert!(size == 3, "Invalid size for mov ma: {}", size);
-
-    let value = ((bytes[2] as i16) << 8) | (bytes[1] as i16);
-    println!("mov ax, [{}]", value);
+    simulator
 }
 
-fn process_mov_am(bytes: &[u8], size: u8, memory: &mut RegisterFile) {
-    memory.move_ip_by_n(bytes.len());
-    debug!("; Processing mov am:");
-    debug_bytes(bytes);
-    assert!(size == 3, "Invalid size for mov am: {}", size);
+fn dump_memory(simulator: &Simulator, options: &Options) {
+    let start = options.from as usize;
+    let end = (start + options.length as usize).min(simulator.memory.as_bytes().len());
+    let bytes = &simulator.memory.as_bytes()[start..end];
 
-    let value = ((bytes[2] as i16) << 8) | (bytes[1] as i16);
-    println!("mov [{}], ax", value);
-}
-
-fn process_jmp(bytes: &[u8], size: u8, op: Opcode, memory: &mut RegisterFile) {
-    memory.move_ip_by_n(bytes.len());
-    debug_bytes(bytes);
-    assert!(size == 2, "Invalid size for jmp: {}", size);
-
-    let value = bytes[1] as i8;
-    match op {
-        Opcode::Jne => {
-            if !memory.get_flag(registers::Flag::Zero) {
-                memory.move_ip_by_n(value as usize);
-            }
+    if let Some(path) = &options.output {
+        if let Err(e) = std::fs::write(path, bytes) {
+            eprintln!("Error writing dump '{}': {}", path, e);
+            std::process::exit(1);
         }
+        return;
+    }
 
-        _ => {
-            panic!("Unsupported jump opcode: {:?}", op);
-        }
+    for (i, row) in bytes.chunks(16).enumerate() {
+        println!("{:05x}: {}", start + i * 16, format_hex(row));
     }
-    println!("{} {}", op, value);
 }
 
-fn process_ir(bytes: &[u8], size: u8, op: Opcode, memory: &mut RegisterFile) {
-    memory.move_ip_by_n(bytes.len());
-    debug!("; Processing ir:");
-    debug_bytes(bytes);
-    assert!(
-        size == 3 || size == 2,
-        "Invalid size for {} ir {}",
-        op,
-        size
-    );
-
-    let is_arithmetic = op == Opcode::AddIA || op == Opcode::SubIA || op == Opcode::CmpIA;
+fn main() {
+    let options = parse_args();
 
-    let w = if op == Opcode::AddIA {
-        bytes[0] & 0b1
-    } else {
-        (bytes[0] >> 3) & 0b1
-    };
-    let reg = bytes[0] & 0b111;
-    let value = if size == 3 {
-        ((bytes[2] as i16) << 8) | (bytes[1] as i16)
-    } else {
-        let _b = bytes[1] as i8;
-        _b as i16
+    let mut logger = match &options.log {
+        Some(path) => Logger::file(path, options.verbosity).unwrap_or_else(|e| {
+            eprintln!("Error opening log file '{}': {}", path, e);
+            std::process::exit(1);
+        }),
+        None => Logger::stderr(options.verbosity),
     };
 
-    let dest = if is_arithmetic {
-        if size == 3 {
-            Register::AX
-        } else {
-            Register::AL
+    let file = match read_file(&options.path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("Error reading file: {}", e);
+            std::process::exit(1);
         }
-    } else {
-        retrieve_register(reg, w).expect("Failed to get destination register")
     };
 
-    println!("{} {}, {}", op, dest, value);
-    move_data(dest, value, memory);
-}
+    logger.log(
+        Verbosity::Verbose,
+        format_args!("; File read successfully, size: {} bytes", file.len()),
+    );
 
-fn with_sign(n: i16) -> String {
-    if n >= 0 {
-        format!("+ {}", n)
-    } else {
-        format!("- {}", -n)
+    if options.command == Command::Disasm {
+        disassemble(&file, &options, &mut logger);
+        return;
     }
-}
 
-fn move_data(dest: Register, value: i16, memory: &mut RegisterFile) {
-    debug!("; Moving data: {:016b} to {}", value, dest);
-    memory.set(dest, value as u16);
-    if DEBUG {
-        print_memory_hex(&memory.raw_memory());
+    let simulator = run(&file, &options, &mut logger);
+    match options.command {
+        Command::Exec | Command::Trace => {
+            for line in format_registers(&simulator.registers) {
+                println!("{}", line);
+            }
+        }
+        Command::Dump => dump_memory(&simulator, &options),
+        Command::Disasm => {}
     }
-}
-
-fn perform_arithmetic(op: Opcode, dest: Register, value: i16, memory: &mut RegisterFile) {
-    let current_value = memory.get(dest) as i16;
-    let result = match op {
-        Opcode::AddRmR | Opcode::AddIA | Opcode::AddIRm => current_value + value,
-        Opcode::SubRmR | Opcode::SubIA | Opcode::SubIRm => current_value - value,
-        Opcode::CmpRmR | Opcode::CmpIA | Opcode::CmpIRm => current_value - value,
-
-        _ => panic!("Unsupported arithmetic operation: {:?}", op),
-    };
-    memory.set_flags_from_result(result);
-    if DEBUG {
-        memory.print_flags();
+    if options.dump_regs {
+        for line in format_registers(&simulator.registers) {
+            logger.log(Verbosity::Normal, format_args!("{}", line));
+        }
     }
-    if op != Opcode::CmpRmR {
-        move_data(dest, result, memory)
-    };
 }
@@ -0,0 +1,66 @@
+// The 8086 has a 20-bit address bus
+pub const MEMORY_SIZE: usize = 1 << 20;
+
+pub struct Memory {
+    bytes: Vec<u8>,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            bytes: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    pub fn read_u8(&self, address: u32) -> u8 {
+        self.bytes[address as usize % MEMORY_SIZE]
+    }
+
+    pub fn write_u8(&mut self, address: u32, value: u8) {
+        self.bytes[address as usize % MEMORY_SIZE] = value;
+    }
+
+    pub fn read_u16(&self, address: u32) -> u16 {
+        let lo = self.read_u8(address);
+        let hi = self.read_u8(address.wrapping_add(1));
+        u16::from_le_bytes([lo, hi])
+    }
+
+    pub fn write_u16(&mut self, address: u32, value: u16) {
+        let [lo, hi] = value.to_le_bytes();
+        self.write_u8(address, lo);
+        self.write_u8(address.wrapping_add(1), hi);
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn load(&mut self, address: u32, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            self.write_u8(address.wrapping_add(i as u32), *byte);
+        }
+    }
+
+    pub fn read(&self, address: u32, w: u8) -> u16 {
+        if w == 1 {
+            self.read_u16(address)
+        } else {
+            self.read_u8(address) as u16
+        }
+    }
+
+    pub fn write(&mut self, address: u32, w: u8, value: u16) {
+        if w == 1 {
+            self.write_u16(address, value);
+        } else {
+            self.write_u8(address, value as u8);
+        }
+    }
+}
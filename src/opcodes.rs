@@ -18,6 +18,94 @@ pub enum Opcode {
     CmpRmR,
     CmpIRm,
     CmpIA,
+    AndRmR,
+    AndIA,
+    AndIRm,
+    OrRmR,
+    OrIA,
+    OrIRm,
+    XorRmR,
+    XorIA,
+    XorIRm,
+    TestRmR,
+    TestIA,
+    TestIRm,
+    Not,
+    AdcRmR,
+    AdcIA,
+    AdcIRm,
+    SbbRmR,
+    SbbIA,
+    SbbIRm,
+    IncR,  // Register
+    IncRm, // Register or Memory
+    DecR,
+    DecRm,
+    Neg,
+    Mul,
+    Imul,
+    Div,
+    Idiv,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Shl,
+    Shr,
+    Sar,
+    Movs,
+    Cmps,
+    Scas,
+    Lods,
+    Stos,
+    Call,      // Direct within Segment
+    CallRm,    // Indirect within Segment
+    CallFar,   // Direct Intersegment
+    CallFarRm, // Indirect Intersegment
+    Jmp,
+    JmpShort,
+    JmpRm,
+    JmpFar,
+    JmpFarRm,
+    Ret,  // Within Segment, with or without an immediate added to SP
+    Retf, // Intersegment
+    Int,  // Type specified
+    Int3, // Type 3
+    Into, // On overflow
+    Iret,
+    XchgRmR, // Register or Memory with Register
+    XchgAR,  // Register with Accumulator
+    Xlat,
+    Lea,
+    Lds,
+    Les,
+    Lahf,
+    Sahf,
+    Pushf,
+    Popf,
+    Cbw,
+    Cwd,
+    Daa,
+    Das,
+    Aaa,
+    Aas,
+    Aam,
+    Aad,
+    Clc,
+    Stc,
+    Cmc,
+    Cld,
+    Std,
+    Cli,
+    Sti,
+    Hlt,
+    Wait,
+    Esc, // Escape to an external coprocessor such as the 8087
+    Nop,
+    In,    // Fixed Port
+    InDx,  // Variable Port
+    Out,   // Fixed Port
+    OutDx, // Variable Port
     Je,
     Jl,
     Jle,
@@ -38,17 +126,63 @@ pub enum Opcode {
     Loopz,
     Loopnz,
     Jcxz,
+    PushR,  // Register
+    PushSr, // Segment Register
+    PushRm, // Register or Memory
+    PopR,
+    PopSr,
+    PopRm,
+    MovSr,   // Register or Memory to/from Segment Register
+    Segment, // Segment override prefix
+    Repeat,  // REP/REPE/REPNE prefix
+    Lock,    // Bus lock prefix
+}
+
+impl Opcode {
+    // MOVS, CMPS, SCAS, LODS and STOS, which take their operands from SI, DI and AL/AX
+    pub fn is_string(&self) -> bool {
+        matches!(
+            self,
+            Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Lods | Opcode::Stos
+        )
+    }
 }
 
 impl fmt::Display for Opcode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Opcode::MovRmR | Opcode::MovIR | Opcode::MovIRm | Opcode::MovAM | Opcode::MovMA => {
+            Opcode::MovRmR
+            | Opcode::MovIR
+            | Opcode::MovIRm
+            | Opcode::MovAM
+            | Opcode::MovMA
+            | Opcode::MovSr => {
                 write!(f, "mov")
             }
             Opcode::AddRmR | Opcode::AddIRm | Opcode::AddIA => write!(f, "add"),
             Opcode::SubRmR | Opcode::SubIRm | Opcode::SubIA => write!(f, "sub"),
             Opcode::CmpRmR | Opcode::CmpIRm | Opcode::CmpIA => write!(f, "cmp"),
+            Opcode::AndRmR | Opcode::AndIRm | Opcode::AndIA => write!(f, "and"),
+            Opcode::OrRmR | Opcode::OrIRm | Opcode::OrIA => write!(f, "or"),
+            Opcode::XorRmR | Opcode::XorIRm | Opcode::XorIA => write!(f, "xor"),
+            Opcode::TestRmR | Opcode::TestIRm | Opcode::TestIA => write!(f, "test"),
+            Opcode::Not => write!(f, "not"),
+            Opcode::AdcRmR | Opcode::AdcIRm | Opcode::AdcIA => write!(f, "adc"),
+            Opcode::SbbRmR | Opcode::SbbIRm | Opcode::SbbIA => write!(f, "sbb"),
+            Opcode::IncR | Opcode::IncRm => write!(f, "inc"),
+            Opcode::DecR | Opcode::DecRm => write!(f, "dec"),
+            Opcode::Neg => write!(f, "neg"),
+            Opcode::Mul => write!(f, "mul"),
+            Opcode::Imul => write!(f, "imul"),
+            Opcode::Div => write!(f, "div"),
+            Opcode::Idiv => write!(f, "idiv"),
+            Opcode::Rol => write!(f, "rol"),
+            Opcode::Ror => write!(f, "ror"),
+            Opcode::Rcl => write!(f, "rcl"),
+            Opcode::Rcr => write!(f, "rcr"),
+            Opcode::Shl => write!(f, "shl"),
+            Opcode::Shr => write!(f, "shr"),
+            Opcode::Sar => write!(f, "sar"),
             Opcode::Je => write!(f, "je"),
             Opcode::Jl => write!(f, "jl"),
             Opcode::Jle => write!(f, "jle"),
@@ -69,6 +203,58 @@ impl fmt::Display for Opcode {
             Opcode::Loopz => write!(f, "loopz"),
             Opcode::Loopnz => write!(f, "loopnz"),
             Opcode::Jcxz => write!(f, "jcxz"),
+            Opcode::PushR | Opcode::PushSr | Opcode::PushRm => write!(f, "push"),
+            Opcode::PopR | Opcode::PopSr | Opcode::PopRm => write!(f, "pop"),
+            Opcode::Movs => write!(f, "movs"),
+            Opcode::Cmps => write!(f, "cmps"),
+            Opcode::Scas => write!(f, "scas"),
+            Opcode::Lods => write!(f, "lods"),
+            Opcode::Stos => write!(f, "stos"),
+            Opcode::Call | Opcode::CallRm | Opcode::CallFar | Opcode::CallFarRm => {
+                write!(f, "call")
+            }
+            Opcode::Jmp | Opcode::JmpShort | Opcode::JmpRm | Opcode::JmpFar | Opcode::JmpFarRm => {
+                write!(f, "jmp")
+            }
+            Opcode::Ret => write!(f, "ret"),
+            Opcode::Retf => write!(f, "retf"),
+            Opcode::Int => write!(f, "int"),
+            Opcode::Int3 => write!(f, "int3"),
+            Opcode::Into => write!(f, "into"),
+            Opcode::Iret => write!(f, "iret"),
+            Opcode::XchgRmR | Opcode::XchgAR => write!(f, "xchg"),
+            Opcode::Xlat => write!(f, "xlatb"),
+            Opcode::Lea => write!(f, "lea"),
+            Opcode::Lds => write!(f, "lds"),
+            Opcode::Les => write!(f, "les"),
+            Opcode::Lahf => write!(f, "lahf"),
+            Opcode::Sahf => write!(f, "sahf"),
+            Opcode::Pushf => write!(f, "pushf"),
+            Opcode::Popf => write!(f, "popf"),
+            Opcode::Cbw => write!(f, "cbw"),
+            Opcode::Cwd => write!(f, "cwd"),
+            Opcode::Daa => write!(f, "daa"),
+            Opcode::Das => write!(f, "das"),
+            Opcode::Aaa => write!(f, "aaa"),
+            Opcode::Aas => write!(f, "aas"),
+            Opcode::Aam => write!(f, "aam"),
+            Opcode::Aad => write!(f, "aad"),
+            Opcode::Clc => write!(f, "clc"),
+            Opcode::Stc => write!(f, "stc"),
+            Opcode::Cmc => write!(f, "cmc"),
+            Opcode::Cld => write!(f, "cld"),
+            Opcode::Std => write!(f, "std"),
+            Opcode::Cli => write!(f, "cli"),
+            Opcode::Sti => write!(f, "sti"),
+            Opcode::Hlt => write!(f, "hlt"),
+            Opcode::Wait => write!(f, "wait"),
+            Opcode::Esc => write!(f, "esc"),
+            Opcode::Nop => write!(f, "nop"),
+            Opcode::In | Opcode::InDx => write!(f, "in"),
+            Opcode::Out | Opcode::OutDx => write!(f, "out"),
+            Opcode::Segment => write!(f, "segment"),
+            Opcode::Repeat => write!(f, "rep"),
+            Opcode::Lock => write!(f, "lock"),
         }
     }
 }
@@ -87,6 +273,23 @@ pub static OPCODE_TRIE: Lazy<BitTrie> = Lazy::new(|| {
     trie.insert(0b0010110, 7, Opcode::SubIA);
     trie.insert(0b001110, 6, Opcode::CmpRmR);
     trie.insert(0b0011110, 7, Opcode::CmpIA);
+    trie.insert(0b001000, 6, Opcode::AndRmR);
+    trie.insert(0b0010010, 7, Opcode::AndIA);
+    trie.insert(0b000010, 6, Opcode::OrRmR);
+    trie.insert(0b0000110, 7, Opcode::OrIA);
+    trie.insert(0b001100, 6, Opcode::XorRmR);
+    trie.insert(0b0011010, 7, Opcode::XorIA);
+    trie.insert(0b1000010, 7, Opcode::TestRmR);
+    trie.insert(0b1010100, 7, Opcode::TestIA);
+    trie.insert(0b1111011, 7, Opcode::TestIRm);
+    trie.insert(0b000100, 6, Opcode::AdcRmR);
+    trie.insert(0b0001010, 7, Opcode::AdcIA);
+    trie.insert(0b000110, 6, Opcode::SbbRmR);
+    trie.insert(0b0001110, 7, Opcode::SbbIA);
+    trie.insert(0b01000, 5, Opcode::IncR);
+    trie.insert(0b01001, 5, Opcode::DecR);
+    trie.insert(0b1111111, 7, Opcode::IncRm);
+    trie.insert(0b110100, 6, Opcode::Rol);
     trie.insert(0b01110100, 8, Opcode::Je);
     trie.insert(0b01111100, 8, Opcode::Jl);
     trie.insert(0b01111110, 8, Opcode::Jle);
@@ -107,5 +310,71 @@ pub static OPCODE_TRIE: Lazy<BitTrie> = Lazy::new(|| {
     trie.insert(0b11100001, 8, Opcode::Loopz);
     trie.insert(0b11100000, 8, Opcode::Loopnz);
     trie.insert(0b11100011, 8, Opcode::Jcxz);
+    trie.insert(0b01010, 5, Opcode::PushR);
+    trie.insert(0b01011, 5, Opcode::PopR);
+    trie.insert(0b00000110, 8, Opcode::PushSr);
+    trie.insert(0b00001110, 8, Opcode::PushSr);
+    trie.insert(0b00010110, 8, Opcode::PushSr);
+    trie.insert(0b00011110, 8, Opcode::PushSr);
+    trie.insert(0b00000111, 8, Opcode::PopSr);
+    trie.insert(0b00010111, 8, Opcode::PopSr);
+    trie.insert(0b00011111, 8, Opcode::PopSr);
+    trie.insert(0b10001111, 8, Opcode::PopRm);
+    trie.insert(0b10001100, 8, Opcode::MovSr);
+    trie.insert(0b10001110, 8, Opcode::MovSr);
+    trie.insert(0b00100110, 8, Opcode::Segment);
+    trie.insert(0b00101110, 8, Opcode::Segment);
+    trie.insert(0b00110110, 8, Opcode::Segment);
+    trie.insert(0b00111110, 8, Opcode::Segment);
+    trie.insert(0b1010010, 7, Opcode::Movs);
+    trie.insert(0b1010011, 7, Opcode::Cmps);
+    trie.insert(0b1010111, 7, Opcode::Scas);
+    trie.insert(0b1010110, 7, Opcode::Lods);
+    trie.insert(0b1010101, 7, Opcode::Stos);
+    trie.insert(0b1111001, 7, Opcode::Repeat);
+    trie.insert(0b11101000, 8, Opcode::Call);
+    trie.insert(0b10011010, 8, Opcode::CallFar);
+    trie.insert(0b11101001, 8, Opcode::Jmp);
+    trie.insert(0b11101011, 8, Opcode::JmpShort);
+    trie.insert(0b11101010, 8, Opcode::JmpFar);
+    trie.insert(0b1100001, 7, Opcode::Ret);
+    trie.insert(0b1100101, 7, Opcode::Retf);
+    trie.insert(0b11001101, 8, Opcode::Int);
+    trie.insert(0b11001100, 8, Opcode::Int3);
+    trie.insert(0b11001110, 8, Opcode::Into);
+    trie.insert(0b11001111, 8, Opcode::Iret);
+    trie.insert(0b1000011, 7, Opcode::XchgRmR);
+    trie.insert(0b10010, 5, Opcode::XchgAR);
+    trie.insert(0b11010111, 8, Opcode::Xlat);
+    trie.insert(0b10001101, 8, Opcode::Lea);
+    trie.insert(0b11000101, 8, Opcode::Lds);
+    trie.insert(0b11000100, 8, Opcode::Les);
+    trie.insert(0b10011111, 8, Opcode::Lahf);
+    trie.insert(0b10011110, 8, Opcode::Sahf);
+    trie.insert(0b10011100, 8, Opcode::Pushf);
+    trie.insert(0b10011101, 8, Opcode::Popf);
+    trie.insert(0b10011000, 8, Opcode::Cbw);
+    trie.insert(0b10011001, 8, Opcode::Cwd);
+    trie.insert(0b00100111, 8, Opcode::Daa);
+    trie.insert(0b00101111, 8, Opcode::Das);
+    trie.insert(0b00110111, 8, Opcode::Aaa);
+    trie.insert(0b00111111, 8, Opcode::Aas);
+    trie.insert(0b11010100, 8, Opcode::Aam);
+    trie.insert(0b11010101, 8, Opcode::Aad);
+    trie.insert(0b11111000, 8, Opcode::Clc);
+    trie.insert(0b11111001, 8, Opcode::Stc);
+    trie.insert(0b11110101, 8, Opcode::Cmc);
+    trie.insert(0b11111100, 8, Opcode::Cld);
+    trie.insert(0b11111101, 8, Opcode::Std);
+    trie.insert(0b11111010, 8, Opcode::Cli);
+    trie.insert(0b11111011, 8, Opcode::Sti);
+    trie.insert(0b11110100, 8, Opcode::Hlt);
+    trie.insert(0b10011011, 8, Opcode::Wait);
+    trie.insert(0b11110000, 8, Opcode::Lock);
+    trie.insert(0b11011, 5, Opcode::Esc);
+    trie.insert(0b1110010, 7, Opcode::In);
+    trie.insert(0b1110110, 7, Opcode::InDx);
+    trie.insert(0b1110011, 7, Opcode::Out);
+    trie.insert(0b1110111, 7, Opcode::OutDx);
     trie
 });
@@ -0,0 +1,19 @@
+// The 8086 has a separate 64 KiB I/O address space reached through IN and OUT.
+// Device models implement `PortBus` and are attached with `Simulator::set_port_bus`.
+pub trait PortBus {
+    // `w` is 0 for a byte access and 1 for a word access
+    fn read(&mut self, port: u16, w: u8) -> u16;
+    fn write(&mut self, port: u16, w: u8, value: u16);
+}
+
+// Nothing attached: reads see a floating bus of all ones and writes are dropped
+#[derive(Default)]
+pub struct OpenBus;
+
+impl PortBus for OpenBus {
+    fn read(&mut self, _port: u16, w: u8) -> u16 {
+        if w == 1 { 0xFFFF } else { 0x00FF }
+    }
+
+    fn write(&mut self, _port: u16, _w: u8, _value: u16) {}
+}
@@ -1,7 +1,10 @@
+// `#[bitfield]` expands the `B8` field types inside redundant parentheses.
+#![allow(unused_parens)]
+
 use modular_bitfield::prelude::*;
 use std::fmt;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Register {
     AL,
     CL,
@@ -20,13 +23,23 @@ pub enum Register {
     SI,
     DI,
     IP,
+    ES,
+    CS,
+    SS,
+    DS,
 }
 
+#[derive(Copy, Clone, Debug)]
 pub enum Flag {
-    Carry = 0b0000_0001,
-    Zero = 0b0000_0010,
-    Sign = 0b0000_0100,
-    Parity = 0b0000_1000,
+    Carry = 0b0000_0000_0000_0001,
+    Parity = 0b0000_0000_0000_0100,
+    AuxCarry = 0b0000_0000_0001_0000,
+    Zero = 0b0000_0000_0100_0000,
+    Sign = 0b0000_0000_1000_0000,
+    Trap = 0b0000_0001_0000_0000,
+    Interrupt = 0b0000_0010_0000_0000,
+    Direction = 0b0000_0100_0000_0000,
+    Overflow = 0b0000_1000_0000_0000,
 }
 
 impl fmt::Display for Register {
@@ -49,6 +62,10 @@ impl fmt::Display for Register {
             Register::SI => "si",
             Register::DI => "di",
             Register::IP => "ip",
+            Register::ES => "es",
+            Register::CS => "cs",
+            Register::SS => "ss",
+            Register::DS => "ds",
         };
         write!(f, "{}", s)
     }
@@ -77,7 +94,15 @@ pub static REGISTERS: [[Register; 8]; 2] = [
     ],
 ];
 
-#[derive(Copy, Clone)]
+pub static SEGMENT_REGISTERS: [Register; 4] = [
+    Register::ES, // 0b00
+    Register::CS, // 0b01
+    Register::SS, // 0b10
+    Register::DS, // 0b11
+];
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum EAC {
     BXSI,
     BXDI,
@@ -105,6 +130,30 @@ impl fmt::Display for EAC {
     }
 }
 
+impl EAC {
+    pub fn address(&self, registers: &RegisterFile) -> u16 {
+        use Register::*;
+        match self {
+            EAC::BXSI => registers.get(BX).wrapping_add(registers.get(SI)),
+            EAC::BXDI => registers.get(BX).wrapping_add(registers.get(DI)),
+            EAC::BPSI => registers.get(BP).wrapping_add(registers.get(SI)),
+            EAC::BPDI => registers.get(BP).wrapping_add(registers.get(DI)),
+            EAC::SI => registers.get(SI),
+            EAC::DI => registers.get(DI),
+            EAC::BPOrDA => registers.get(BP),
+            EAC::BX => registers.get(BX),
+        }
+    }
+
+    // BP-based addressing goes through the stack segment, everything else through DS
+    pub fn default_segment(&self) -> Register {
+        match self {
+            EAC::BPSI | EAC::BPDI | EAC::BPOrDA => Register::SS,
+            _ => Register::DS,
+        }
+    }
+}
+
 pub static EACS: [EAC; 8] = [
     EAC::BXSI,   // 0b00
     EAC::BXDI,   // 0b01
@@ -129,6 +178,7 @@ impl RegisterRow {
     }
 }
 
+#[derive(Clone, Debug)]
 pub struct RegisterFile {
     ax: RegisterRow, // AX (AL, AH)
     cx: RegisterRow, // CX (CL, CH)
@@ -139,7 +189,17 @@ pub struct RegisterFile {
     si: RegisterRow, // SI
     di: RegisterRow, // DI
     ip: RegisterRow,
-    flags: u8, // FLAGS register
+    es: RegisterRow, // ES
+    cs: RegisterRow, // CS
+    ss: RegisterRow, // SS
+    ds: RegisterRow, // DS
+    flags: u16,      // FLAGS register
+}
+
+impl Default for RegisterFile {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl RegisterFile {
@@ -154,6 +214,10 @@ impl RegisterFile {
             si: RegisterRow::new(),
             di: RegisterRow::new(),
             ip: RegisterRow::new(),
+            es: RegisterRow::new(),
+            cs: RegisterRow::new(),
+            ss: RegisterRow::new(),
+            ds: RegisterRow::new(),
             flags: 0, // Initialize FLAGS to 0
         }
     }
@@ -178,6 +242,10 @@ impl RegisterFile {
             SI => self.si.get(),
             DI => self.di.get(),
             IP => self.ip.get(),
+            ES => self.es.get(),
+            CS => self.cs.get(),
+            SS => self.ss.get(),
+            DS => self.ds.get(),
         }
     }
 
@@ -201,48 +269,122 @@ impl RegisterFile {
             SI => self.si = RegisterRow::from_bytes(value.to_le_bytes()),
             DI => self.di = RegisterRow::from_bytes(value.to_le_bytes()),
             IP => self.ip = RegisterRow::from_bytes(value.to_le_bytes()),
+            ES => self.es = RegisterRow::from_bytes(value.to_le_bytes()),
+            CS => self.cs = RegisterRow::from_bytes(value.to_le_bytes()),
+            SS => self.ss = RegisterRow::from_bytes(value.to_le_bytes()),
+            DS => self.ds = RegisterRow::from_bytes(value.to_le_bytes()),
         }
     }
 
+    pub fn physical_address(&self, segment: Register, offset: u16) -> u32 {
+        (((self.get(segment) as u32) << 4) + offset as u32) & 0xFFFFF
+    }
+
     pub fn move_ip_by_n(&mut self, n: usize) {
-        let current_ip = self.ip.get() as i16;
-        let new_ip = current_ip + n as i16;
-        println!("; Moving IP from {} to {}", current_ip, new_ip);
+        // Negative jump offsets arrive sign-extended, so the truncating add wraps back correctly
+        let current_ip = self.ip.get();
+        let new_ip = current_ip.wrapping_add(n as u16);
         self.ip = RegisterRow::from_bytes(new_ip.to_le_bytes());
     }
 
     fn set_flag(&mut self, flag: Flag) {
-        self.flags |= flag as u8;
+        self.flags |= flag as u16;
     }
 
     fn clear_flag(&mut self, flag: Flag) {
-        self.flags &= !(flag as u8)
+        self.flags &= !(flag as u16)
+    }
+
+    pub fn set_flag_to(&mut self, flag: Flag, value: bool) {
+        if value {
+            self.set_flag(flag);
+        } else {
+            self.clear_flag(flag);
+        }
     }
 
     pub fn get_flag(&self, flag: Flag) -> bool {
-        self.flags & (flag as u8) != 0
+        self.flags & (flag as u16) != 0
     }
 
-    pub fn print_flags(&self) {
-        println!("; Flags: {:08b}", self.flags);
+    pub fn flags(&self) -> u16 {
+        self.flags
     }
 
-    pub fn set_flags_from_result(&mut self, result: i16) {
-        if result == 0 {
-            self.set_flag(Flag::Zero);
-        } else {
-            self.clear_flag(Flag::Zero);
-        }
-        if result < 0 {
-            self.set_flag(Flag::Sign);
-        } else {
-            self.clear_flag(Flag::Sign);
-        }
-        if result % 2 == 0 {
-            self.set_flag(Flag::Parity);
-        } else {
-            self.clear_flag(Flag::Parity);
-        }
+    // Bits without a flag are dropped, so POPF and IRET cannot set reserved bits
+    pub fn set_flags(&mut self, flags: u16) {
+        self.flags = flags & 0x0FD5;
+    }
+
+    // FLAGS as PUSHF and interrupts store it; the 8086 reads bits 1 and 12-15 as 1
+    pub fn stack_flags(&self) -> u16 {
+        self.flags | 0xF002
+    }
+
+    // Set flags as letters, e.g. "CZ", in the order the 8086 documentation uses
+    pub fn flags_string(&self) -> String {
+        [
+            (Flag::Carry, 'C'),
+            (Flag::Parity, 'P'),
+            (Flag::AuxCarry, 'A'),
+            (Flag::Zero, 'Z'),
+            (Flag::Sign, 'S'),
+            (Flag::Trap, 'T'),
+            (Flag::Interrupt, 'I'),
+            (Flag::Direction, 'D'),
+            (Flag::Overflow, 'O'),
+        ]
+        .iter()
+        .filter(|(flag, _)| self.get_flag(*flag))
+        .map(|(_, letter)| *letter)
+        .collect()
+    }
+
+    // Sets ZF, SF and PF from a result of the given width
+    pub fn set_flags_from_result(&mut self, result: u16, w: u8) {
+        let result = result & width_mask(w);
+        self.set_flag_to(Flag::Zero, result == 0);
+        self.set_flag_to(Flag::Sign, result & sign_bit(w) != 0);
+        // PF only looks at the low byte, even for 16-bit results
+        self.set_flag_to(Flag::Parity, (result as u8).count_ones().is_multiple_of(2));
+    }
+
+    // `carry` is the carry-in: 0 for ADD, CF for ADC
+    pub fn add_with_flags(&mut self, a: u16, b: u16, carry: u16, w: u8) -> u16 {
+        let (a, b) = (a & width_mask(w), b & width_mask(w));
+        let full = a as u32 + b as u32 + carry as u32;
+        let result = full as u16 & width_mask(w);
+
+        self.set_flag_to(Flag::Carry, full > width_mask(w) as u32);
+        self.set_flag_to(Flag::AuxCarry, (a ^ b ^ result) & 0x10 != 0);
+        self.set_flag_to(
+            Flag::Overflow,
+            (a ^ result) & (b ^ result) & sign_bit(w) != 0,
+        );
+        self.set_flags_from_result(result, w);
+        result
+    }
+
+    // `borrow` is the borrow-in: 0 for SUB and CMP, CF for SBB
+    pub fn sub_with_flags(&mut self, a: u16, b: u16, borrow: u16, w: u8) -> u16 {
+        let (a, b) = (a & width_mask(w), b & width_mask(w));
+        let result = a.wrapping_sub(b).wrapping_sub(borrow) & width_mask(w);
+
+        self.set_flag_to(Flag::Carry, b as u32 + borrow as u32 > a as u32);
+        self.set_flag_to(Flag::AuxCarry, (a ^ b ^ result) & 0x10 != 0);
+        self.set_flag_to(Flag::Overflow, (a ^ b) & (a ^ result) & sign_bit(w) != 0);
+        self.set_flags_from_result(result, w);
+        result
+    }
+
+    // AND, OR, XOR and TEST always clear CF and OF; AF is undefined and cleared here
+    pub fn logic_with_flags(&mut self, result: u16, w: u8) -> u16 {
+        let result = result & width_mask(w);
+        self.set_flag_to(Flag::Carry, false);
+        self.set_flag_to(Flag::Overflow, false);
+        self.set_flag_to(Flag::AuxCarry, false);
+        self.set_flags_from_result(result, w);
+        result
     }
 
     pub fn raw_memory(&self) -> [u8; 16] {
@@ -267,10 +409,90 @@ impl RegisterFile {
     }
 }
 
-pub fn retrieve_register(index: u8, w: u8) -> Result<Register, String> {
-    REGISTERS
-        .get(w as usize)
-        .and_then(|row| row.get(index as usize))
-        .copied()
-        .ok_or_else(|| format!("Invalid register index: {}", index).into())
+pub fn width_mask(w: u8) -> u16 {
+    if w == 1 { 0xFFFF } else { 0x00FF }
+}
+
+pub fn sign_bit(w: u8) -> u16 {
+    if w == 1 { 0x8000 } else { 0x0080 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each helper runs one operation on fresh registers and returns the result
+    // with the flags it set, as letters
+    fn add(a: u16, b: u16, carry: u16, w: u8) -> (u16, String) {
+        let mut registers = RegisterFile::new();
+        let result = registers.add_with_flags(a, b, carry, w);
+        (result, registers.flags_string())
+    }
+
+    fn sub(a: u16, b: u16, borrow: u16, w: u8) -> (u16, String) {
+        let mut registers = RegisterFile::new();
+        let result = registers.sub_with_flags(a, b, borrow, w);
+        (result, registers.flags_string())
+    }
+
+    fn zsp(result: u16, w: u8) -> String {
+        let mut registers = RegisterFile::new();
+        registers.set_flags_from_result(result, w);
+        registers.flags_string()
+    }
+
+    #[test]
+    fn add_8_bit_edges() {
+        assert_eq!(add(0x7F, 1, 0, 0), (0x80, "ASO".into()));
+        assert_eq!(add(0xFF, 1, 0, 0), (0x00, "CPAZ".into()));
+        assert_eq!(add(0x80, 0x80, 0, 0), (0x00, "CPZO".into()));
+        // Operands are masked to the width, so the high byte cannot leak into the result
+        assert_eq!(add(0x12FF, 0x3401, 0, 0), (0x00, "CPAZ".into()));
+    }
+
+    #[test]
+    fn add_16_bit_edges() {
+        assert_eq!(add(0x7FFF, 1, 0, 1), (0x8000, "PASO".into()));
+        assert_eq!(add(0xFFFF, 1, 0, 1), (0x0000, "CPAZ".into()));
+        assert_eq!(add(0x00FF, 1, 0, 1), (0x0100, "PA".into()));
+    }
+
+    #[test]
+    fn add_with_carry_in() {
+        assert_eq!(add(0xFFFF, 0, 1, 1), (0x0000, "CPAZ".into()));
+        assert_eq!(add(0x7E, 1, 1, 0), (0x80, "ASO".into()));
+    }
+
+    #[test]
+    fn sub_edges() {
+        assert_eq!(sub(0x8000, 1, 0, 1), (0x7FFF, "PAO".into()));
+        assert_eq!(sub(0x80, 1, 0, 0), (0x7F, "AO".into()));
+        assert_eq!(sub(0x00, 1, 0, 0), (0xFF, "CPAS".into()));
+        assert_eq!(sub(0x1234, 0x1234, 0, 1), (0x0000, "PZ".into()));
+    }
+
+    #[test]
+    fn sub_with_borrow_in() {
+        // b + borrow equal to a does not borrow
+        assert_eq!(sub(0x10, 0x0F, 1, 0), (0x00, "PAZ".into()));
+        assert_eq!(sub(0x0000, 0x0000, 1, 1), (0xFFFF, "CPAS".into()));
+        assert_eq!(sub(0x8000, 0x7FFF, 1, 1), (0x0000, "PAZO".into()));
+        assert_eq!(sub(0x05, 0x05, 1, 0), (0xFF, "CPAS".into()));
+    }
+
+    #[test]
+    fn parity_uses_the_low_byte_only() {
+        assert_eq!(zsp(0x0300, 1), "P");
+        assert_eq!(zsp(0x7F01, 1), "");
+        assert_eq!(zsp(0xFF03, 1), "PS");
+        assert_eq!(zsp(0xFF00, 0), "PZ");
+    }
+
+    #[test]
+    fn logic_clears_carry_overflow_and_aux() {
+        let mut registers = RegisterFile::new();
+        registers.set_flags(0x0FD5);
+        assert_eq!(registers.logic_with_flags(0x1280, 0), 0x80);
+        assert_eq!(registers.flags_string(), "STID");
+    }
 }
@@ -0,0 +1,1471 @@
+use crate::decoder::decode;
+use crate::error::{DecodeError, ExecError};
+use crate::instruction::{Instruction, MemoryOperand, Operand, Repeat};
+use crate::memory::Memory;
+use crate::opcodes::Opcode;
+use crate::ports::{OpenBus, PortBus};
+use crate::registers::{Flag, Register, RegisterFile, sign_bit, width_mask};
+use std::collections::HashMap;
+use std::ops::Range;
+
+// Where an instruction reads or writes its operand
+#[derive(Copy, Clone, Debug)]
+pub enum Location {
+    Register(Register),
+    Memory(u32),
+}
+
+// Host-side replacement for a guest interrupt handler
+pub type InterruptHandler = Box<dyn FnMut(&mut RegisterFile, &mut Memory)>;
+
+pub struct Simulator {
+    pub registers: RegisterFile,
+    pub memory: Memory,
+    handlers: HashMap<u8, InterruptHandler>,
+    halted: bool,
+    ports: Box<dyn PortBus>,
+    image: Range<u32>, // Physical addresses of the last program loaded
+}
+
+impl Default for Simulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self {
+            registers: RegisterFile::new(),
+            memory: Memory::new(),
+            handlers: HashMap::new(),
+            halted: false,
+            ports: Box::new(OpenBus),
+            image: 0..0,
+        }
+    }
+
+    // Routes IN and OUT to `ports` instead of the default open bus
+    pub fn set_port_bus(&mut self, ports: Box<dyn PortBus>) {
+        self.ports = ports;
+    }
+
+    // Set by HLT; only an interrupt would resume the processor
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    // Runs `handler` instead of the guest's vector whenever interrupt `vector` is raised
+    pub fn set_interrupt_handler(&mut self, vector: u8, handler: InterruptHandler) {
+        self.handlers.insert(vector, handler);
+    }
+
+    pub fn clear_interrupt_handler(&mut self, vector: u8) {
+        self.handlers.remove(&vector);
+    }
+
+    // Copies a program to CS:org and points IP at its first byte
+    pub fn load_program(&mut self, program: &[u8], org: u16) {
+        let address = self.registers.physical_address(Register::CS, org);
+        self.memory.load(address, program);
+        self.image = address..address + program.len() as u32;
+        self.registers.set(Register::IP, org);
+        self.halted = false;
+    }
+
+    pub fn instruction_address(&self) -> u32 {
+        let ip = self.registers.get(Register::IP);
+        self.registers.physical_address(Register::CS, ip)
+    }
+
+    // Decodes the instruction at CS:IP without executing it
+    pub fn fetch(&self) -> Result<Instruction, DecodeError> {
+        decode(self.memory.as_bytes(), self.instruction_address() as usize)
+    }
+
+    pub fn execute(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        self.registers.move_ip_by_n(instruction.length);
+
+        let w = instruction.w;
+        match instruction.opcode {
+            Opcode::MovRmR
+            | Opcode::MovIR
+            | Opcode::MovIRm
+            | Opcode::MovAM
+            | Opcode::MovMA
+            | Opcode::MovSr => {
+                let (dest, source) = self.operands(instruction)?;
+                let value = self.read_operand(source, instruction)?;
+                self.write_operand(dest, instruction, value)?;
+            }
+            Opcode::AddRmR
+            | Opcode::AddIA
+            | Opcode::AddIRm
+            | Opcode::SubRmR
+            | Opcode::SubIA
+            | Opcode::SubIRm
+            | Opcode::CmpRmR
+            | Opcode::CmpIA
+            | Opcode::CmpIRm
+            | Opcode::AndRmR
+            | Opcode::AndIA
+            | Opcode::AndIRm
+            | Opcode::OrRmR
+            | Opcode::OrIA
+            | Opcode::OrIRm
+            | Opcode::XorRmR
+            | Opcode::XorIA
+            | Opcode::XorIRm
+            | Opcode::TestRmR
+            | Opcode::TestIA
+            | Opcode::TestIRm
+            | Opcode::AdcRmR
+            | Opcode::AdcIA
+            | Opcode::AdcIRm
+            | Opcode::SbbRmR
+            | Opcode::SbbIA
+            | Opcode::SbbIRm => {
+                let (dest, source) = self.operands(instruction)?;
+                let current_value = self.read_operand(dest, instruction)?;
+                let value = self.read_operand(source, instruction)?;
+                let registers = &mut self.registers;
+                let carry = registers.get_flag(Flag::Carry) as u16;
+                let result = match instruction.opcode {
+                    Opcode::AddRmR | Opcode::AddIA | Opcode::AddIRm => {
+                        registers.add_with_flags(current_value, value, 0, w)
+                    }
+                    Opcode::AdcRmR | Opcode::AdcIA | Opcode::AdcIRm => {
+                        registers.add_with_flags(current_value, value, carry, w)
+                    }
+                    Opcode::SubRmR
+                    | Opcode::SubIA
+                    | Opcode::SubIRm
+                    | Opcode::CmpRmR
+                    | Opcode::CmpIA
+                    | Opcode::CmpIRm => registers.sub_with_flags(current_value, value, 0, w),
+                    Opcode::SbbRmR | Opcode::SbbIA | Opcode::SbbIRm => {
+                        registers.sub_with_flags(current_value, value, carry, w)
+                    }
+                    Opcode::OrRmR | Opcode::OrIA | Opcode::OrIRm => {
+                        registers.logic_with_flags(current_value | value, w)
+                    }
+                    Opcode::XorRmR | Opcode::XorIA | Opcode::XorIRm => {
+                        registers.logic_with_flags(current_value ^ value, w)
+                    }
+                    _ => registers.logic_with_flags(current_value & value, w),
+                };
+                // CMP and TEST only keep the flags
+                if !matches!(
+                    instruction.opcode,
+                    Opcode::CmpRmR
+                        | Opcode::CmpIA
+                        | Opcode::CmpIRm
+                        | Opcode::TestRmR
+                        | Opcode::TestIA
+                        | Opcode::TestIRm
+                ) {
+                    self.write_operand(dest, instruction, result)?;
+                }
+            }
+            Opcode::IncR | Opcode::IncRm | Opcode::DecR | Opcode::DecRm => {
+                let (dest, _) = self.operands(instruction)?;
+                let value = self.read_operand(dest, instruction)?;
+                // INC and DEC update every arithmetic flag except CF
+                let carry = self.registers.get_flag(Flag::Carry);
+                let result = match instruction.opcode {
+                    Opcode::IncR | Opcode::IncRm => self.registers.add_with_flags(value, 1, 0, w),
+                    _ => self.registers.sub_with_flags(value, 1, 0, w),
+                };
+                self.registers.set_flag_to(Flag::Carry, carry);
+                self.write_operand(dest, instruction, result)?;
+            }
+            Opcode::Neg => {
+                // Subtracting from zero sets CF for any non-zero operand, as the 8086 does
+                let (dest, _) = self.operands(instruction)?;
+                let value = self.read_operand(dest, instruction)?;
+                let result = self.registers.sub_with_flags(0, value, 0, w);
+                self.write_operand(dest, instruction, result)?;
+            }
+            Opcode::Mul | Opcode::Imul => self.multiply(instruction)?,
+            Opcode::Div | Opcode::Idiv => self.divide(instruction)?,
+            Opcode::Rol
+            | Opcode::Ror
+            | Opcode::Rcl
+            | Opcode::Rcr
+            | Opcode::Shl
+            | Opcode::Shr
+            | Opcode::Sar => self.shift(instruction)?,
+            Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Lods | Opcode::Stos => {
+                self.string(instruction)
+            }
+            Opcode::Call
+            | Opcode::CallRm
+            | Opcode::CallFar
+            | Opcode::CallFarRm
+            | Opcode::Jmp
+            | Opcode::JmpShort
+            | Opcode::JmpRm
+            | Opcode::JmpFar
+            | Opcode::JmpFarRm => self.transfer(instruction)?,
+            Opcode::Ret | Opcode::Retf => {
+                let ip = self.pop_word();
+                self.registers.set(Register::IP, ip);
+                if instruction.opcode == Opcode::Retf {
+                    let cs = self.pop_word();
+                    self.registers.set(Register::CS, cs);
+                }
+                if let Some(release) = instruction.dest() {
+                    let release = self.read_operand(release, instruction)?;
+                    let sp = self.registers.get(Register::SP).wrapping_add(release);
+                    self.registers.set(Register::SP, sp);
+                }
+            }
+            Opcode::Int | Opcode::Int3 | Opcode::Into => {
+                let vector = match (instruction.opcode, instruction.dest()) {
+                    (Opcode::Int, Some(vector)) => self.read_operand(vector, instruction)? as u8,
+                    (Opcode::Int3, _) => 3,
+                    (Opcode::Into, _) if self.registers.get_flag(Flag::Overflow) => 4,
+                    (Opcode::Into, _) => return Ok(()),
+                    _ => {
+                        return Err(ExecError::InvalidOperand {
+                            instruction: *instruction,
+                        });
+                    }
+                };
+                self.interrupt(vector, instruction)?;
+            }
+            Opcode::Iret => {
+                let ip = self.pop_word();
+                let cs = self.pop_word();
+                let flags = self.pop_word();
+                self.registers.set(Register::IP, ip);
+                self.registers.set(Register::CS, cs);
+                self.registers.set_flags(flags);
+            }
+            Opcode::XchgRmR | Opcode::XchgAR => {
+                let (dest, source) = self.operands(instruction)?;
+                let a = self.read_operand(dest, instruction)?;
+                let b = self.read_operand(source, instruction)?;
+                self.write_operand(dest, instruction, b)?;
+                self.write_operand(source, instruction, a)?;
+            }
+            Opcode::Xlat => {
+                let offset = self
+                    .registers
+                    .get(Register::BX)
+                    .wrapping_add(self.registers.get(Register::AL));
+                let segment = instruction.prefixes.segment.unwrap_or(Register::DS);
+                let address = self.registers.physical_address(segment, offset);
+                self.registers
+                    .set(Register::AL, self.memory.read_u8(address) as u16);
+            }
+            Opcode::Lea => {
+                let (dest, source) = self.operands(instruction)?;
+                let Operand::Memory(memory) = source else {
+                    return Err(ExecError::InvalidOperand {
+                        instruction: *instruction,
+                    });
+                };
+                let offset = self.effective_address(&memory);
+                self.write_operand(dest, instruction, offset)?;
+            }
+            Opcode::Lds | Opcode::Les => {
+                let (dest, source) = self.operands(instruction)?;
+                let Location::Memory(address) = self.location(source, instruction)? else {
+                    return Err(ExecError::InvalidOperand {
+                        instruction: *instruction,
+                    });
+                };
+                let offset = self.memory.read_u16(address);
+                let segment = self.memory.read_u16(address + 2);
+                self.write_operand(dest, instruction, offset)?;
+                let segment_register = match instruction.opcode {
+                    Opcode::Lds => Register::DS,
+                    _ => Register::ES,
+                };
+                self.registers.set(segment_register, segment);
+            }
+            Opcode::Lahf => {
+                // SF, ZF, AF, PF and CF, with bit 1 reading as 1
+                let flags = (self.registers.flags() & 0xD5) | 0x02;
+                self.registers.set(Register::AH, flags);
+            }
+            Opcode::Sahf => {
+                let ah = self.registers.get(Register::AH);
+                let flags = (self.registers.flags() & 0xFF00) | (ah & 0xD5);
+                self.registers.set_flags(flags);
+            }
+            Opcode::Pushf => self.push_word(self.registers.stack_flags()),
+            Opcode::Popf => {
+                let flags = self.pop_word();
+                self.registers.set_flags(flags);
+            }
+            Opcode::Cbw => {
+                let al = self.registers.get(Register::AL);
+                self.registers
+                    .set(Register::AX, al as u8 as i8 as i16 as u16);
+            }
+            Opcode::Cwd => {
+                let negative = self.registers.get(Register::AX) & 0x8000 != 0;
+                self.registers
+                    .set(Register::DX, if negative { 0xFFFF } else { 0 });
+            }
+            Opcode::Daa | Opcode::Das | Opcode::Aaa | Opcode::Aas | Opcode::Aam | Opcode::Aad => {
+                self.adjust(instruction)?
+            }
+            Opcode::Clc => self.registers.set_flag_to(Flag::Carry, false),
+            Opcode::Stc => self.registers.set_flag_to(Flag::Carry, true),
+            Opcode::Cmc => {
+                let carry = self.registers.get_flag(Flag::Carry);
+                self.registers.set_flag_to(Flag::Carry, !carry);
+            }
+            Opcode::Cld => self.registers.set_flag_to(Flag::Direction, false),
+            Opcode::Std => self.registers.set_flag_to(Flag::Direction, true),
+            Opcode::Cli => self.registers.set_flag_to(Flag::Interrupt, false),
+            Opcode::Sti => self.registers.set_flag_to(Flag::Interrupt, true),
+            Opcode::Hlt => self.halted = true,
+            // Without a coprocessor attached WAIT returns at once and ESC does nothing
+            Opcode::Wait | Opcode::Esc | Opcode::Nop => {}
+            Opcode::In | Opcode::InDx => {
+                let (dest, port) = self.operands(instruction)?;
+                let port = self.read_operand(port, instruction)?;
+                let value = self.ports.read(port, w);
+                self.write_operand(dest, instruction, value)?;
+            }
+            Opcode::Out | Opcode::OutDx => {
+                let (port, source) = self.operands(instruction)?;
+                let port = self.read_operand(port, instruction)?;
+                let value = self.read_operand(source, instruction)?;
+                self.ports.write(port, w, value);
+            }
+            Opcode::Not => {
+                // NOT leaves every flag untouched
+                let (dest, _) = self.operands(instruction)?;
+                let value = self.read_operand(dest, instruction)?;
+                self.write_operand(dest, instruction, !value)?;
+            }
+            Opcode::Je
+            | Opcode::Jl
+            | Opcode::Jle
+            | Opcode::Jb
+            | Opcode::Jbe
+            | Opcode::Jp
+            | Opcode::Jo
+            | Opcode::Js
+            | Opcode::Jne
+            | Opcode::Jnl
+            | Opcode::Jg
+            | Opcode::Jnb
+            | Opcode::Ja
+            | Opcode::Jnp
+            | Opcode::Jno
+            | Opcode::Jns
+            | Opcode::Loop
+            | Opcode::Loopz
+            | Opcode::Loopnz
+            | Opcode::Jcxz => self.jump(instruction)?,
+            Opcode::PushR | Opcode::PushSr | Opcode::PushRm => {
+                let (operand, _) = self.operands(instruction)?;
+                // The 8086 decrements SP before reading it, so PUSH SP stores the new value
+                let value = match operand {
+                    Operand::Register(Register::SP) => {
+                        self.registers.get(Register::SP).wrapping_sub(2)
+                    }
+                    _ => self.read_operand(operand, instruction)?,
+                };
+                self.push_word(value);
+            }
+            Opcode::PopR | Opcode::PopSr | Opcode::PopRm => {
+                let (operand, _) = self.operands(instruction)?;
+                let value = self.pop_word();
+                self.write_operand(operand, instruction, value)?;
+            }
+            Opcode::Segment | Opcode::Repeat | Opcode::Lock => {
+                return Err(ExecError::Unsupported {
+                    instruction: *instruction,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn operands(&self, instruction: &Instruction) -> Result<(Operand, Operand), ExecError> {
+        let dest = instruction.dest().ok_or(ExecError::InvalidOperand {
+            instruction: *instruction,
+        })?;
+        Ok((dest, instruction.source().unwrap_or(dest)))
+    }
+
+    fn jump(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        let Some(Operand::Relative(displacement)) = instruction.dest() else {
+            return Err(ExecError::InvalidOperand {
+                instruction: *instruction,
+            });
+        };
+
+        let registers = &mut self.registers;
+        let zero = registers.get_flag(Flag::Zero);
+        let carry = registers.get_flag(Flag::Carry);
+        let less = registers.get_flag(Flag::Sign) != registers.get_flag(Flag::Overflow);
+
+        let taken = match instruction.opcode {
+            Opcode::Je => zero,
+            Opcode::Jl => less,
+            Opcode::Jle => less || zero,
+            Opcode::Jb => carry,
+            Opcode::Jbe => carry || zero,
+            Opcode::Jp => registers.get_flag(Flag::Parity),
+            Opcode::Jo => registers.get_flag(Flag::Overflow),
+            Opcode::Js => registers.get_flag(Flag::Sign),
+            Opcode::Jne => !zero,
+            Opcode::Jnl => !less,
+            Opcode::Jg => !less && !zero,
+            Opcode::Jnb => !carry,
+            Opcode::Ja => !carry && !zero,
+            Opcode::Jnp => !registers.get_flag(Flag::Parity),
+            Opcode::Jno => !registers.get_flag(Flag::Overflow),
+            Opcode::Jns => !registers.get_flag(Flag::Sign),
+            Opcode::Jcxz => registers.get(Register::CX) == 0,
+            Opcode::Loop | Opcode::Loopz | Opcode::Loopnz => {
+                // The LOOP family decrements CX without touching the flags
+                let cx = registers.get(Register::CX).wrapping_sub(1);
+                registers.set(Register::CX, cx);
+                match instruction.opcode {
+                    Opcode::Loopz => cx != 0 && zero,
+                    Opcode::Loopnz => cx != 0 && !zero,
+                    _ => cx != 0,
+                }
+            }
+
+            _ => {
+                return Err(ExecError::Unsupported {
+                    instruction: *instruction,
+                });
+            }
+        };
+
+        if taken {
+            registers.move_ip_by_n(displacement as usize);
+        }
+        Ok(())
+    }
+
+    // MUL and IMUL widen AL or AX into AX or DX:AX; CF and OF report whether the
+    // upper half carries any significant bits
+    fn multiply(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        let (source, _) = self.operands(instruction)?;
+        let value = self.read_operand(source, instruction)?;
+        let w = instruction.w;
+        let signed = instruction.opcode == Opcode::Imul;
+
+        let (low, high) = if w == 1 {
+            let ax = self.registers.get(Register::AX);
+            let product = if signed {
+                (ax as i16 as i32 * value as i16 as i32) as u32
+            } else {
+                ax as u32 * value as u32
+            };
+            self.registers.set(Register::AX, product as u16);
+            self.registers.set(Register::DX, (product >> 16) as u16);
+            (product as u16, (product >> 16) as u16)
+        } else {
+            let al = self.registers.get(Register::AL);
+            let product = if signed {
+                (al as u8 as i8 as i16 * value as u8 as i8 as i16) as u16
+            } else {
+                al * (value & 0xFF)
+            };
+            self.registers.set(Register::AX, product);
+            (product & 0xFF, product >> 8)
+        };
+
+        let significant = if signed {
+            // The upper half must be the sign extension of the lower half
+            let extension = if low & sign_bit(w) != 0 {
+                width_mask(w)
+            } else {
+                0
+            };
+            high != extension
+        } else {
+            high != 0
+        };
+        self.registers.set_flag_to(Flag::Carry, significant);
+        self.registers.set_flag_to(Flag::Overflow, significant);
+        Ok(())
+    }
+
+    // DIV and IDIV split AX or DX:AX into a quotient and remainder. A zero divisor or a
+    // quotient that does not fit raises interrupt 0 instead of writing anything back
+    fn divide(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        let (source, _) = self.operands(instruction)?;
+        let divisor = self.read_operand(source, instruction)? & width_mask(instruction.w);
+        let signed = instruction.opcode == Opcode::Idiv;
+
+        let (dividend, divisor, quotient_range) = match (instruction.w, signed) {
+            (1, false) => {
+                let dx = self.registers.get(Register::DX) as i64;
+                let ax = self.registers.get(Register::AX) as i64;
+                ((dx << 16) | ax, divisor as i64, 0..=0xFFFF)
+            }
+            (1, true) => {
+                let dx = self.registers.get(Register::DX) as i16 as i64;
+                let ax = self.registers.get(Register::AX) as i64;
+                // The 8086 faults on a quotient of -32768, unlike later processors
+                ((dx << 16) | ax, divisor as i16 as i64, -0x7FFF..=0x7FFF)
+            }
+            (_, false) => {
+                let ax = self.registers.get(Register::AX) as i64;
+                (ax, divisor as i64, 0..=0xFF)
+            }
+            (_, true) => {
+                let ax = self.registers.get(Register::AX) as i16 as i64;
+                (ax, divisor as u8 as i8 as i64, -0x7F..=0x7F)
+            }
+        };
+
+        // Rust's integer division truncates towards zero, matching IDIV
+        if divisor == 0 || !quotient_range.contains(&(dividend / divisor)) {
+            return self.interrupt(0, instruction);
+        }
+        let quotient = (dividend / divisor) as u16;
+        let remainder = (dividend % divisor) as u16;
+
+        if instruction.w == 1 {
+            self.registers.set(Register::AX, quotient);
+            self.registers.set(Register::DX, remainder);
+        } else {
+            self.registers.set(Register::AL, quotient);
+            self.registers.set(Register::AH, remainder);
+        }
+        Ok(())
+    }
+
+    // Shifts and rotates one bit at a time so CF always holds the last bit shifted out.
+    // The 8086 does not mask the count in CL, and a count of 0 leaves the flags alone.
+    fn shift(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        let (dest, count) = self.operands(instruction)?;
+        let count = self.read_operand(count, instruction)? & 0xFF;
+        if count == 0 {
+            return Ok(());
+        }
+
+        let w = instruction.w;
+        let (mask, sign) = (width_mask(w), sign_bit(w));
+        let mut value = self.read_operand(dest, instruction)? & mask;
+        let mut carry = self.registers.get_flag(Flag::Carry);
+
+        for _ in 0..count {
+            let (msb, lsb) = (value & sign != 0, value & 1 != 0);
+            value = match instruction.opcode {
+                Opcode::Rol => ((value << 1) | msb as u16) & mask,
+                Opcode::Ror => (value >> 1) | if lsb { sign } else { 0 },
+                Opcode::Rcl => ((value << 1) | carry as u16) & mask,
+                Opcode::Rcr => (value >> 1) | if carry { sign } else { 0 },
+                Opcode::Shl => (value << 1) & mask,
+                Opcode::Shr => value >> 1,
+                _ => (value >> 1) | (value & sign),
+            };
+            carry = match instruction.opcode {
+                Opcode::Rol | Opcode::Rcl | Opcode::Shl => msb,
+                _ => lsb,
+            };
+        }
+
+        // OF is only defined for a count of 1; it reflects the last step like the hardware
+        let overflow = match instruction.opcode {
+            Opcode::Rol | Opcode::Rcl | Opcode::Shl => (value & sign != 0) != carry,
+            _ => (value & sign != 0) != (value & (sign >> 1) != 0),
+        };
+        self.registers.set_flag_to(Flag::Carry, carry);
+        self.registers.set_flag_to(Flag::Overflow, overflow);
+        if matches!(instruction.opcode, Opcode::Shl | Opcode::Shr | Opcode::Sar) {
+            self.registers.set_flags_from_result(value, w);
+        }
+        self.write_operand(dest, instruction, value)
+    }
+
+    // Runs a string instruction once, or CX times under a REP prefix. REPE and REPNE
+    // also stop CMPS and SCAS as soon as ZF no longer matches the prefix.
+    fn string(&mut self, instruction: &Instruction) {
+        let repeat = instruction.prefixes.repeat;
+        if repeat.is_none() {
+            self.string_step(instruction);
+            return;
+        }
+
+        while self.registers.get(Register::CX) != 0 {
+            self.string_step(instruction);
+            let cx = self.registers.get(Register::CX).wrapping_sub(1);
+            self.registers.set(Register::CX, cx);
+
+            if matches!(instruction.opcode, Opcode::Cmps | Opcode::Scas) {
+                let zero = self.registers.get_flag(Flag::Zero);
+                if (repeat == Some(Repeat::Rep) && !zero) || (repeat == Some(Repeat::Repne) && zero)
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    fn string_step(&mut self, instruction: &Instruction) {
+        let w = instruction.w;
+        let size = if w == 1 { 2u16 } else { 1 };
+        let step = if self.registers.get_flag(Flag::Direction) {
+            size.wrapping_neg()
+        } else {
+            size
+        };
+
+        // The source segment can be overridden, the destination is always ES:DI
+        let si = self.registers.get(Register::SI);
+        let di = self.registers.get(Register::DI);
+        let segment = instruction.prefixes.segment.unwrap_or(Register::DS);
+        let source = self.registers.physical_address(segment, si);
+        let dest = self.registers.physical_address(Register::ES, di);
+        let accumulator = if w == 1 { Register::AX } else { Register::AL };
+
+        match instruction.opcode {
+            Opcode::Movs => {
+                let value = self.memory.read(source, w);
+                self.memory.write(dest, w, value);
+            }
+            Opcode::Cmps => {
+                let (a, b) = (self.memory.read(source, w), self.memory.read(dest, w));
+                self.registers.sub_with_flags(a, b, 0, w);
+            }
+            Opcode::Scas => {
+                let a = self.registers.get(accumulator);
+                let b = self.memory.read(dest, w);
+                self.registers.sub_with_flags(a, b, 0, w);
+            }
+            Opcode::Lods => {
+                let value = self.memory.read(source, w);
+                self.registers.set(accumulator, value);
+            }
+            _ => {
+                let value = self.registers.get(accumulator);
+                self.memory.write(dest, w, value);
+            }
+        }
+
+        if matches!(
+            instruction.opcode,
+            Opcode::Movs | Opcode::Cmps | Opcode::Lods
+        ) {
+            self.registers.set(Register::SI, si.wrapping_add(step));
+        }
+        if matches!(
+            instruction.opcode,
+            Opcode::Movs | Opcode::Cmps | Opcode::Scas | Opcode::Stos
+        ) {
+            self.registers.set(Register::DI, di.wrapping_add(step));
+        }
+    }
+
+    // Decimal adjusts. The 8086 performs every correction with its ALU, so the flags
+    // the manuals call undefined are those of the correcting addition or subtraction.
+    fn adjust(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        let registers = &mut self.registers;
+        let al = registers.get(Register::AL);
+        let ah = registers.get(Register::AH);
+        let aux_carry = registers.get_flag(Flag::AuxCarry);
+        let carry = registers.get_flag(Flag::Carry);
+        let low_adjust = al & 0x0F > 9 || aux_carry;
+
+        match instruction.opcode {
+            Opcode::Daa | Opcode::Das => {
+                // With AF set the 8086 compares against 0x9F rather than 0x99
+                let limit = if aux_carry { 0x9F } else { 0x99 };
+                let high_adjust = al > limit || carry;
+                let correction =
+                    (if low_adjust { 0x06 } else { 0 }) + (if high_adjust { 0x60 } else { 0 });
+                let result = match instruction.opcode {
+                    Opcode::Daa => registers.add_with_flags(al, correction, 0, 0),
+                    _ => registers.sub_with_flags(al, correction, 0, 0),
+                };
+                registers.set(Register::AL, result);
+                registers.set_flag_to(Flag::AuxCarry, low_adjust);
+                registers.set_flag_to(Flag::Carry, high_adjust);
+            }
+            Opcode::Aaa | Opcode::Aas => {
+                let correction = if low_adjust { 6 } else { 0 };
+                let (result, ah) = match instruction.opcode {
+                    Opcode::Aaa => (
+                        registers.add_with_flags(al, correction, 0, 0),
+                        ah.wrapping_add(low_adjust as u16),
+                    ),
+                    _ => (
+                        registers.sub_with_flags(al, correction, 0, 0),
+                        ah.wrapping_sub(low_adjust as u16),
+                    ),
+                };
+                registers.set(Register::AL, result & 0x0F);
+                registers.set(Register::AH, ah);
+                registers.set_flag_to(Flag::AuxCarry, low_adjust);
+                registers.set_flag_to(Flag::Carry, low_adjust);
+            }
+            _ => {
+                let base = match instruction.dest() {
+                    Some(base) => self.read_operand(base, instruction)? & 0xFF,
+                    None => 10,
+                };
+                let registers = &mut self.registers;
+                if instruction.opcode == Opcode::Aad {
+                    let product = ah.wrapping_mul(base) & 0xFF;
+                    let result = registers.add_with_flags(al, product, 0, 0);
+                    registers.set(Register::AX, result);
+                } else {
+                    // A base of 0 divides by zero just like DIV
+                    let Some(quotient) = al.checked_div(base) else {
+                        return self.interrupt(0, instruction);
+                    };
+                    registers.set(Register::AH, quotient);
+                    let result = registers.logic_with_flags(al % base, 0);
+                    registers.set(Register::AL, result);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Pushes FLAGS, CS and IP and continues at the handler in the interrupt vector
+    // table. A host handler takes precedence and returns straight to the next
+    // instruction. A vector still pointing at 0000:0000, or one whose entry lies in
+    // the loaded program, is treated as having no handler: a program loaded over
+    // the table would otherwise supply its own bytes as handler addresses.
+    pub fn interrupt(&mut self, vector: u8, instruction: &Instruction) -> Result<(), ExecError> {
+        if let Some(handler) = self.handlers.get_mut(&vector) {
+            handler(&mut self.registers, &mut self.memory);
+            return Ok(());
+        }
+
+        let entry = vector as u32 * 4;
+        let ip = self.memory.read_u16(entry);
+        let cs = self.memory.read_u16(entry + 2);
+        let overlapped = entry < self.image.end && self.image.start < entry + 4;
+        if (ip == 0 && cs == 0) || overlapped {
+            return Err(ExecError::UnhandledInterrupt {
+                instruction: *instruction,
+                vector,
+            });
+        }
+
+        self.push_word(self.registers.stack_flags());
+        self.push_word(self.registers.get(Register::CS));
+        self.push_word(self.registers.get(Register::IP));
+        self.registers.set_flag_to(Flag::Interrupt, false);
+        self.registers.set_flag_to(Flag::Trap, false);
+        self.registers.set(Register::CS, cs);
+        self.registers.set(Register::IP, ip);
+        Ok(())
+    }
+
+    // Unconditional CALL and JMP. IP already points past the instruction, which is
+    // both the base for relative targets and the return address a CALL pushes.
+    fn transfer(&mut self, instruction: &Instruction) -> Result<(), ExecError> {
+        let (target, _) = self.operands(instruction)?;
+        let ip = self.registers.get(Register::IP);
+
+        let (cs, new_ip) = match (instruction.opcode, target) {
+            (_, Operand::Relative(displacement)) => (None, ip.wrapping_add(displacement as u16)),
+            (_, Operand::Far(segment, offset)) => (Some(segment), offset),
+            (Opcode::CallFarRm | Opcode::JmpFarRm, _) => {
+                let Location::Memory(address) = self.location(target, instruction)? else {
+                    return Err(ExecError::InvalidOperand {
+                        instruction: *instruction,
+                    });
+                };
+                let offset = self.memory.read_u16(address);
+                (Some(self.memory.read_u16(address + 2)), offset)
+            }
+            _ => (None, self.read_operand(target, instruction)?),
+        };
+
+        if matches!(
+            instruction.opcode,
+            Opcode::Call | Opcode::CallRm | Opcode::CallFar | Opcode::CallFarRm
+        ) {
+            if cs.is_some() {
+                self.push_word(self.registers.get(Register::CS));
+            }
+            self.push_word(ip);
+        }
+        if let Some(cs) = cs {
+            self.registers.set(Register::CS, cs);
+        }
+        self.registers.set(Register::IP, new_ip);
+        Ok(())
+    }
+
+    // The offset within the segment, as LEA computes it
+    pub fn effective_address(&self, memory: &MemoryOperand) -> u16 {
+        let base = memory
+            .eac
+            .map(|eac| eac.address(&self.registers))
+            .unwrap_or(0);
+        base.wrapping_add(memory.displacement.unwrap_or(0) as u16)
+    }
+
+    pub fn location(
+        &self,
+        operand: Operand,
+        instruction: &Instruction,
+    ) -> Result<Location, ExecError> {
+        let location = match operand {
+            Operand::Register(reg) => Location::Register(reg),
+            Operand::Memory(memory) => {
+                let offset = self.effective_address(&memory);
+                let segment = instruction
+                    .prefixes
+                    .segment
+                    .unwrap_or(memory.default_segment());
+                Location::Memory(self.registers.physical_address(segment, offset))
+            }
+            _ => {
+                return Err(ExecError::InvalidOperand {
+                    instruction: *instruction,
+                });
+            }
+        };
+        Ok(location)
+    }
+
+    pub fn read_operand(
+        &self,
+        operand: Operand,
+        instruction: &Instruction,
+    ) -> Result<u16, ExecError> {
+        let value = match operand {
+            Operand::Immediate(value) | Operand::Relative(value) => value as u16,
+            _ => match self.location(operand, instruction)? {
+                Location::Register(reg) => self.registers.get(reg),
+                Location::Memory(address) => self.memory.read(address, instruction.w),
+            },
+        };
+        Ok(value)
+    }
+
+    pub fn write_operand(
+        &mut self,
+        operand: Operand,
+        instruction: &Instruction,
+        value: u16,
+    ) -> Result<(), ExecError> {
+        match self.location(operand, instruction)? {
+            Location::Register(reg) => self.registers.set(reg, value),
+            Location::Memory(address) => self.memory.write(address, instruction.w, value),
+        }
+        Ok(())
+    }
+
+    pub fn push_word(&mut self, value: u16) {
+        let sp = self.registers.get(Register::SP).wrapping_sub(2);
+        self.registers.set(Register::SP, sp);
+        let address = self.registers.physical_address(Register::SS, sp);
+        self.memory.write_u16(address, value);
+    }
+
+    pub fn pop_word(&mut self) -> u16 {
+        let sp = self.registers.get(Register::SP);
+        self.registers.set(Register::SP, sp.wrapping_add(2));
+        let address = self.registers.physical_address(Register::SS, sp);
+        self.memory.read_u16(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    // Loads `bytes`, sets the given registers and flags (as letters, e.g. "CA") and
+    // executes one instruction. Flags not named are cleared.
+    fn step(
+        simulator: &mut Simulator,
+        bytes: &[u8],
+        registers: &[(Register, u16)],
+        flags: &str,
+    ) -> Result<(), ExecError> {
+        simulator.load_program(bytes, 0x100);
+        for &(reg, value) in registers {
+            simulator.registers.set(reg, value);
+        }
+        simulator.registers.set_flags(0);
+        for letter in flags.chars() {
+            let flag = match letter {
+                'C' => Flag::Carry,
+                'P' => Flag::Parity,
+                'A' => Flag::AuxCarry,
+                'Z' => Flag::Zero,
+                'S' => Flag::Sign,
+                'T' => Flag::Trap,
+                'I' => Flag::Interrupt,
+                'D' => Flag::Direction,
+                'O' => Flag::Overflow,
+                _ => panic!("unexpected input flag {letter}"),
+            };
+            simulator.registers.set_flag_to(flag, true);
+        }
+        let instruction = simulator.fetch().unwrap();
+        simulator.execute(&instruction)
+    }
+
+    // Executes `bytes` once with the given AX and flags and returns AX and the flags afterwards
+    fn run(simulator: &mut Simulator, bytes: &[u8], ax: u16, flags: &str) -> (u16, String) {
+        step(simulator, bytes, &[(Register::AX, ax)], flags).unwrap();
+        (
+            simulator.registers.get(Register::AX),
+            simulator.registers.flags_string(),
+        )
+    }
+
+    // Installs a host handler for `vector` that counts how often it is raised
+    fn count_interrupts(simulator: &mut Simulator, vector: u8) -> Rc<Cell<u32>> {
+        let count = Rc::new(Cell::new(0));
+        let raised = Rc::clone(&count);
+        simulator.set_interrupt_handler(vector, Box::new(move |_, _| raised.set(raised.get() + 1)));
+        count
+    }
+
+    fn check(bytes: &[u8], cases: &[(u16, &str, u16, &str)]) {
+        let mut simulator = Simulator::new();
+        for &(ax, flags, expected_ax, expected_flags) in cases {
+            assert_eq!(
+                run(&mut simulator, bytes, ax, flags),
+                (expected_ax, expected_flags.to_string()),
+                "{bytes:02x?} with ax={ax:#06x} flags={flags:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn daa() {
+        check(
+            &[0x27],
+            &[
+                (0x003C, "", 0x0042, "PA"),
+                (0x009A, "", 0x0000, "CPAZ"),
+                (0x0012, "C", 0x0072, "CP"),
+                // OF follows the signed overflow of adding the correction
+                (0x007A, "", 0x0080, "ASO"),
+                // With AF set the high correction only starts above 0x9F
+                (0x009A, "A", 0x00A0, "PAS"),
+            ],
+        );
+    }
+
+    #[test]
+    fn das() {
+        check(
+            &[0x2F],
+            &[
+                (0x002D, "A", 0x0027, "PA"),
+                (0x009A, "", 0x0034, "CAO"),
+                (0x0000, "C", 0x00A0, "CPS"),
+            ],
+        );
+    }
+
+    #[test]
+    fn aaa() {
+        check(
+            &[0x37],
+            &[
+                (0x000E, "", 0x0104, "CPA"),
+                (0x0005, "", 0x0005, "P"),
+                // ZF, SF, PF and OF come from AL + 6 before the high nibble is cleared
+                (0x007A, "", 0x0100, "CASO"),
+                // The 8086 adds 1 to AH, not 0x106 to AX
+                (0x00FF, "", 0x0105, "CPA"),
+            ],
+        );
+    }
+
+    #[test]
+    fn aas() {
+        check(
+            &[0x3F],
+            &[
+                (0x02FE, "A", 0x0108, "CAS"),
+                (0x0085, "", 0x0005, "S"),
+                (0x0082, "A", 0xFF0C, "CAO"),
+            ],
+        );
+    }
+
+    #[test]
+    fn aam() {
+        check(
+            &[0xD4, 0x0A],
+            &[(0x003F, "", 0x0603, "P"), (0x0000, "CA", 0x0000, "PZ")],
+        );
+        check(&[0xD4, 0x10], &[(0x00A5, "", 0x0A05, "P")]);
+    }
+
+    #[test]
+    fn aam_base_zero_raises_divide_error() {
+        let mut simulator = Simulator::new();
+        let raised = count_interrupts(&mut simulator, 0);
+
+        assert_eq!(
+            run(&mut simulator, &[0xD4, 0x00], 0x1234, ""),
+            (0x1234, "".into())
+        );
+        assert_eq!(raised.get(), 1);
+    }
+
+    #[test]
+    fn aad() {
+        check(
+            &[0xD5, 0x0A],
+            &[
+                (0x0603, "", 0x003F, "P"),
+                (0x0909, "", 0x0063, "PA"),
+                (0x0F0F, "", 0x00A5, "PAS"),
+                // AH * base is truncated to a byte before the add
+                (0x1A10, "", 0x0014, "P"),
+            ],
+        );
+        check(&[0xD5, 0x10], &[(0x0A05, "", 0x00A5, "PS")]);
+    }
+
+    // Runs a MUL or IMUL of AL by BL, or AX by BX, and returns DX, AX and the flags
+    fn multiply(bytes: &[u8], ax: u16, bx: u16) -> (u16, u16, String) {
+        let mut simulator = Simulator::new();
+        let registers = [
+            (Register::AX, ax),
+            (Register::BX, bx),
+            (Register::DX, 0x5555),
+        ];
+        step(&mut simulator, bytes, &registers, "").unwrap();
+        (
+            simulator.registers.get(Register::DX),
+            simulator.registers.get(Register::AX),
+            simulator.registers.flags_string(),
+        )
+    }
+
+    #[test]
+    fn mul_and_imul_8_bit() {
+        let (mul, imul) = ([0xF6, 0xE3], [0xF6, 0xEB]);
+        // The byte forms leave DX alone
+        assert_eq!(multiply(&mul, 0x0010, 0x0F), (0x5555, 0x00F0, "".into()));
+        // 0xFF * 2 needs AH unsigned, but -1 * 2 = -2 is just AL sign-extended
+        assert_eq!(multiply(&mul, 0x00FF, 0x02), (0x5555, 0x01FE, "CO".into()));
+        assert_eq!(multiply(&imul, 0x00FF, 0x02), (0x5555, 0xFFFE, "".into()));
+        // 64 * 2 = 128 fits in AL unsigned but not signed
+        assert_eq!(multiply(&mul, 0x0040, 0x02), (0x5555, 0x0080, "".into()));
+        assert_eq!(multiply(&imul, 0x0040, 0x02), (0x5555, 0x0080, "CO".into()));
+        // Only AL takes part, whatever AH held
+        assert_eq!(multiply(&imul, 0x7F80, 0xFF), (0x5555, 0x0080, "CO".into()));
+    }
+
+    #[test]
+    fn mul_and_imul_16_bit() {
+        let (mul, imul) = ([0xF7, 0xE3], [0xF7, 0xEB]);
+        assert_eq!(
+            multiply(&mul, 0xFFFF, 0xFFFF),
+            (0xFFFE, 0x0001, "CO".into())
+        );
+        assert_eq!(multiply(&imul, 0xFFFF, 0xFFFF), (0x0000, 0x0001, "".into()));
+        assert_eq!(
+            multiply(&imul, 0x8000, 0xFFFF),
+            (0x0000, 0x8000, "CO".into())
+        );
+        assert_eq!(multiply(&imul, 0xFFFE, 0x4000), (0xFFFF, 0x8000, "".into()));
+        assert_eq!(multiply(&mul, 0x1234, 0x0001), (0x0000, 0x1234, "".into()));
+    }
+
+    // Runs a DIV or IDIV of AX by BL, or DX:AX by BX, and returns DX, AX and
+    // whether it raised a divide error
+    fn divide(bytes: &[u8], dx: u16, ax: u16, bx: u16) -> (u16, u16, bool) {
+        let mut simulator = Simulator::new();
+        let raised = count_interrupts(&mut simulator, 0);
+        let registers = [(Register::DX, dx), (Register::AX, ax), (Register::BX, bx)];
+        step(&mut simulator, bytes, &registers, "").unwrap();
+        (
+            simulator.registers.get(Register::DX),
+            simulator.registers.get(Register::AX),
+            raised.get() == 1,
+        )
+    }
+
+    #[test]
+    fn div_8_bit() {
+        let div = [0xF6, 0xF3];
+        assert_eq!(divide(&div, 0, 0x0107, 0x10), (0, 0x0710, false));
+        // 511 / 2 gives the largest quotient that fits, 0xFF rest 1
+        assert_eq!(divide(&div, 0, 0x01FF, 0x02), (0, 0x01FF, false));
+        // A zero divisor or a quotient over 0xFF faults and leaves AX as it was
+        assert_eq!(divide(&div, 0, 0x1234, 0x00), (0, 0x1234, true));
+        assert_eq!(divide(&div, 0, 0x0200, 0x01), (0, 0x0200, true));
+    }
+
+    #[test]
+    fn idiv_8_bit_quotient_limits() {
+        let idiv = [0xF6, 0xFB];
+        assert_eq!(divide(&idiv, 0, 0x00FE, 0x02), (0, 0x007F, false));
+        assert_eq!(divide(&idiv, 0, 0xFF02, 0x02), (0, 0x0081, false));
+        // -256 / 2 = -128 fits in a byte, but the 8086 still faults on it
+        assert_eq!(divide(&idiv, 0, 0xFF00, 0x02), (0, 0xFF00, true));
+        assert_eq!(divide(&idiv, 0, 0x0080, 0xFF), (0, 0x0080, true));
+        // The remainder takes the sign of the dividend: -7 / 2 = -3 rest -1
+        assert_eq!(divide(&idiv, 0, 0xFFF9, 0x02), (0, 0xFFFD, false));
+        assert_eq!(divide(&idiv, 0, 0x0007, 0xFE), (0, 0x01FD, false));
+    }
+
+    #[test]
+    fn div_16_bit() {
+        let div = [0xF7, 0xF3];
+        assert_eq!(
+            divide(&div, 0x0001, 0x0000, 0x0002),
+            (0x0000, 0x8000, false)
+        );
+        assert_eq!(
+            divide(&div, 0x0000, 0x0007, 0x0002),
+            (0x0001, 0x0003, false)
+        );
+        // DX:AX and the divisor are unsigned
+        assert_eq!(
+            divide(&div, 0xFFFE, 0xFFFF, 0xFFFF),
+            (0xFFFE, 0xFFFF, false)
+        );
+        assert_eq!(divide(&div, 0x0002, 0x0000, 0x0002), (0x0002, 0x0000, true));
+        assert_eq!(divide(&div, 0x1234, 0x5678, 0x0000), (0x1234, 0x5678, true));
+    }
+
+    #[test]
+    fn idiv_16_bit_quotient_limits() {
+        let idiv = [0xF7, 0xFB];
+        assert_eq!(
+            divide(&idiv, 0x0000, 0xFFFE, 0x0002),
+            (0x0000, 0x7FFF, false)
+        );
+        assert_eq!(
+            divide(&idiv, 0xFFFF, 0x0002, 0x0002),
+            (0x0000, 0x8001, false)
+        );
+        // -65536 / 2 = -32768 faults on the 8086
+        assert_eq!(
+            divide(&idiv, 0xFFFF, 0x0000, 0x0002),
+            (0xFFFF, 0x0000, true)
+        );
+        assert_eq!(
+            divide(&idiv, 0x0000, 0x8000, 0xFFFF),
+            (0x0000, 0x8000, true)
+        );
+        assert_eq!(
+            divide(&idiv, 0xFFFF, 0xFFF9, 0x0002),
+            (0xFFFF, 0xFFFD, false)
+        );
+    }
+
+    // Runs a shift or rotate of AL or AX with the given CL and input flags, and
+    // returns AX and the flags afterwards
+    fn shift(bytes: &[u8], ax: u16, cl: u16, flags: &str) -> (u16, String) {
+        let mut simulator = Simulator::new();
+        let registers = [(Register::AX, ax), (Register::CX, cl)];
+        step(&mut simulator, bytes, &registers, flags).unwrap();
+        (
+            simulator.registers.get(Register::AX),
+            simulator.registers.flags_string(),
+        )
+    }
+
+    // The /reg field of D0-D3 for each operation, with AL or AX as the operand
+    const ROL: u8 = 0xC0;
+    const ROR: u8 = 0xC8;
+    const RCL: u8 = 0xD0;
+    const RCR: u8 = 0xD8;
+    const SHL: u8 = 0xE0;
+    const SHR: u8 = 0xE8;
+    const SAR: u8 = 0xF8;
+
+    #[test]
+    fn rotate_by_one_overflow() {
+        // Left rotates: OF is the new top bit XOR CF
+        assert_eq!(shift(&[0xD0, ROL], 0x81, 0, ""), (0x03, "CO".into()));
+        assert_eq!(shift(&[0xD0, ROL], 0x40, 0, ""), (0x80, "O".into()));
+        assert_eq!(shift(&[0xD0, ROL], 0xC0, 0, ""), (0x81, "C".into()));
+        assert_eq!(shift(&[0xD0, RCL], 0x80, 0, ""), (0x00, "CO".into()));
+        assert_eq!(shift(&[0xD0, RCL], 0x40, 0, "C"), (0x81, "O".into()));
+        // Right rotates: OF is the new top bit XOR the one below it
+        assert_eq!(shift(&[0xD0, ROR], 0x01, 0, ""), (0x80, "CO".into()));
+        assert_eq!(shift(&[0xD0, ROR], 0x81, 0, ""), (0xC0, "C".into()));
+        assert_eq!(shift(&[0xD0, RCR], 0x01, 0, ""), (0x00, "C".into()));
+        assert_eq!(shift(&[0xD0, RCR], 0x00, 0, "C"), (0x80, "O".into()));
+        assert_eq!(shift(&[0xD0, RCR], 0x80, 0, "C"), (0xC0, "".into()));
+        assert_eq!(shift(&[0xD1, ROR], 0x0001, 0, ""), (0x8000, "CO".into()));
+        // Rotates leave ZF, SF and PF alone
+        assert_eq!(shift(&[0xD0, ROL], 0x00, 0, "PZS"), (0x00, "PZS".into()));
+    }
+
+    #[test]
+    fn shift_by_one_overflow() {
+        // SHL: OF is the new top bit XOR CF
+        assert_eq!(shift(&[0xD0, SHL], 0x40, 0, ""), (0x80, "SO".into()));
+        assert_eq!(shift(&[0xD0, SHL], 0xC0, 0, ""), (0x80, "CS".into()));
+        assert_eq!(shift(&[0xD0, SHL], 0x80, 0, ""), (0x00, "CPZO".into()));
+        // SHR: OF is the old top bit
+        assert_eq!(shift(&[0xD0, SHR], 0x81, 0, ""), (0x40, "CO".into()));
+        assert_eq!(shift(&[0xD0, SHR], 0x01, 0, ""), (0x00, "CPZ".into()));
+        // SAR: the sign is kept, so OF is always clear
+        assert_eq!(shift(&[0xD0, SAR], 0x81, 0, "O"), (0xC0, "CPS".into()));
+        assert_eq!(shift(&[0xD0, SAR], 0x40, 0, ""), (0x20, "".into()));
+        assert_eq!(shift(&[0xD1, SAR], 0x8000, 0, ""), (0xC000, "PS".into()));
+    }
+
+    #[test]
+    fn shift_by_zero_leaves_flags_alone() {
+        for op in [ROL, ROR, RCL, RCR, SHL, SHR, SAR] {
+            assert_eq!(shift(&[0xD2, op], 0x81, 0, "CPZO"), (0x81, "CPZO".into()));
+            assert_eq!(shift(&[0xD3, op], 0x8001, 0x0100, ""), (0x8001, "".into()));
+        }
+    }
+
+    #[test]
+    fn large_counts_are_not_masked() {
+        let carry = |bytes: &[u8], ax, cl, flags| {
+            let (value, flags) = shift(bytes, ax, cl, flags);
+            (value, flags.contains('C'))
+        };
+        // CF holds the last bit shifted out, which is 0 once the count passes the width
+        assert_eq!(carry(&[0xD2, SHL], 0x01, 8, ""), (0x00, true));
+        assert_eq!(carry(&[0xD2, SHL], 0x01, 9, ""), (0x00, false));
+        assert_eq!(carry(&[0xD2, SHR], 0x80, 8, ""), (0x00, true));
+        assert_eq!(carry(&[0xD2, SAR], 0x80, 9, ""), (0xFF, true));
+        assert_eq!(carry(&[0xD3, SHL], 0x0001, 16, ""), (0x0000, true));
+        assert_eq!(carry(&[0xD3, SHL], 0xFFFF, 17, ""), (0x0000, false));
+        assert_eq!(carry(&[0xD3, SHR], 0xFFFF, 255, ""), (0x0000, false));
+        // A later processor masks 32 to 0 and would leave AL at 1
+        assert_eq!(carry(&[0xD2, SHL], 0x01, 32, ""), (0x00, false));
+        // Rotates come back round: 8 bits for ROL, 9 through CF for RCL and RCR
+        assert_eq!(carry(&[0xD2, ROL], 0x81, 8, ""), (0x81, true));
+        assert_eq!(carry(&[0xD2, RCL], 0x5A, 9, "C"), (0x5A, true));
+        assert_eq!(carry(&[0xD2, RCR], 0x81, 17, ""), (0x02, true));
+        assert_eq!(carry(&[0xD3, ROL], 0x8001, 17, ""), (0x0003, true));
+    }
+
+    const DS_BASE: u32 = 0x10000;
+    const ES_BASE: u32 = 0x20000;
+
+    // A simulator with DS at 0x1000 and ES at 0x2000, `source` at DS:0010 and
+    // `dest` at ES:0020
+    fn strings(source: &[u8], dest: &[u8]) -> Simulator {
+        let mut simulator = Simulator::new();
+        simulator.registers.set(Register::DS, (DS_BASE >> 4) as u16);
+        simulator.registers.set(Register::ES, (ES_BASE >> 4) as u16);
+        simulator.memory.load(DS_BASE + 0x10, source);
+        simulator.memory.load(ES_BASE + 0x20, dest);
+        simulator
+    }
+
+    fn bytes_at(simulator: &Simulator, address: u32, length: usize) -> &[u8] {
+        &simulator.memory.as_bytes()[address as usize..][..length]
+    }
+
+    fn si_di_cx(simulator: &Simulator) -> (u16, u16, u16) {
+        (
+            simulator.registers.get(Register::SI),
+            simulator.registers.get(Register::DI),
+            simulator.registers.get(Register::CX),
+        )
+    }
+
+    const INDEXES: [(Register, u16); 2] = [(Register::SI, 0x10), (Register::DI, 0x20)];
+
+    #[test]
+    fn rep_movs_copies_cx_elements() {
+        let mut simulator = strings(b"abcd", b"....");
+        let registers = [INDEXES[0], INDEXES[1], (Register::CX, 3)];
+        step(&mut simulator, &[0xF3, 0xA4], &registers, "").unwrap();
+        assert_eq!(bytes_at(&simulator, ES_BASE + 0x20, 4), b"abc.");
+        assert_eq!(si_di_cx(&simulator), (0x13, 0x23, 0));
+    }
+
+    #[test]
+    fn rep_with_cx_zero_does_nothing() {
+        let mut simulator = strings(b"abcd", b"abcx");
+        let registers = [INDEXES[0], INDEXES[1], (Register::CX, 0)];
+        step(&mut simulator, &[0xF3, 0xA4], &registers, "").unwrap();
+        assert_eq!(bytes_at(&simulator, ES_BASE + 0x20, 4), b"abcx");
+        assert_eq!(si_di_cx(&simulator), (0x10, 0x20, 0));
+
+        // No comparison runs either, so the flags are untouched
+        step(&mut simulator, &[0xF3, 0xA6], &registers, "CS").unwrap();
+        assert_eq!(simulator.registers.flags_string(), "CS");
+        assert_eq!(si_di_cx(&simulator), (0x10, 0x20, 0));
+    }
+
+    #[test]
+    fn direction_flag_steps_backwards() {
+        let mut simulator = strings(b"\x11\x22\x33\x44", b"....");
+        // SI and DI point at the last word of each block
+        let registers = [
+            (Register::SI, 0x12),
+            (Register::DI, 0x22),
+            (Register::CX, 2),
+        ];
+        step(&mut simulator, &[0xF3, 0xA5], &registers, "D").unwrap();
+        assert_eq!(
+            bytes_at(&simulator, ES_BASE + 0x20, 4),
+            [0x11, 0x22, 0x33, 0x44]
+        );
+        assert_eq!(si_di_cx(&simulator), (0x0E, 0x1E, 0));
+
+        let registers = [(Register::SI, 0x13)];
+        step(&mut simulator, &[0xAC], &registers, "D").unwrap();
+        assert_eq!(simulator.registers.get(Register::AL), 0x44);
+        assert_eq!(simulator.registers.get(Register::SI), 0x12);
+    }
+
+    #[test]
+    fn repe_cmps_stops_at_the_first_difference() {
+        let mut simulator = strings(b"abcx", b"abdx");
+        let registers = [INDEXES[0], INDEXES[1], (Register::CX, 4)];
+        step(&mut simulator, &[0xF3, 0xA6], &registers, "").unwrap();
+        // 'c' - 'd' borrows; the pointers are already past the mismatch
+        assert_eq!(si_di_cx(&simulator), (0x13, 0x23, 1));
+        assert_eq!(simulator.registers.flags_string(), "CPAS");
+
+        let mut simulator = strings(b"abcd", b"abcd");
+        step(&mut simulator, &[0xF3, 0xA6], &registers, "").unwrap();
+        assert_eq!(si_di_cx(&simulator), (0x14, 0x24, 0));
+        assert!(simulator.registers.get_flag(Flag::Zero));
+    }
+
+    #[test]
+    fn repne_scas_stops_at_the_first_match() {
+        // SCAS never touches SI
+        let mut simulator = strings(b"", b"abcd");
+        let registers = [INDEXES[1], (Register::CX, 4), (Register::AX, b'c' as u16)];
+        step(&mut simulator, &[0xF2, 0xAE], &registers, "").unwrap();
+        assert_eq!(si_di_cx(&simulator), (0, 0x23, 1));
+        assert!(simulator.registers.get_flag(Flag::Zero));
+
+        // Without a match it runs CX down to 0 and leaves ZF clear
+        let registers = [INDEXES[1], (Register::CX, 4), (Register::AX, b'z' as u16)];
+        step(&mut simulator, &[0xF2, 0xAE], &registers, "").unwrap();
+        assert_eq!(si_di_cx(&simulator), (0, 0x24, 0));
+        assert!(!simulator.registers.get_flag(Flag::Zero));
+
+        // Without a prefix CX is ignored
+        let registers = [INDEXES[1], (Register::CX, 0), (Register::AX, b'a' as u16)];
+        step(&mut simulator, &[0xAE], &registers, "").unwrap();
+        assert_eq!(si_di_cx(&simulator), (0, 0x21, 0));
+        assert!(simulator.registers.get_flag(Flag::Zero));
+    }
+
+    #[test]
+    fn segment_override_only_moves_the_source() {
+        let mut simulator = strings(b"ds", b"..");
+        simulator.memory.load(ES_BASE + 0x10, b"es");
+        let registers = [INDEXES[0], INDEXES[1], (Register::CX, 2)];
+
+        // rep es: movsb reads ES:SI and still writes ES:DI
+        step(&mut simulator, &[0xF3, 0x26, 0xA4], &registers, "").unwrap();
+        assert_eq!(bytes_at(&simulator, ES_BASE + 0x20, 2), b"es");
+        assert_eq!(bytes_at(&simulator, DS_BASE + 0x20, 2), [0, 0]);
+
+        // ds: stosb cannot redirect the destination away from ES:DI
+        let registers = [INDEXES[1], (Register::AX, b'!' as u16)];
+        step(&mut simulator, &[0x3E, 0xAA], &registers, "").unwrap();
+        assert_eq!(bytes_at(&simulator, ES_BASE + 0x20, 2), b"!s");
+        assert_eq!(bytes_at(&simulator, DS_BASE + 0x20, 2), [0, 0]);
+    }
+
+    // A simulator with a stack at 3000:0100 and vector `vector` pointing at 5678:1234
+    fn with_vector(vector: u8) -> Simulator {
+        let mut simulator = Simulator::new();
+        simulator.registers.set(Register::SS, 0x3000);
+        simulator.registers.set(Register::SP, 0x0100);
+        simulator.memory.write_u16(vector as u32 * 4, 0x1234);
+        simulator.memory.write_u16(vector as u32 * 4 + 2, 0x5678);
+        simulator
+    }
+
+    fn cs_ip_sp(simulator: &Simulator) -> (u16, u16, u16) {
+        (
+            simulator.registers.get(Register::CS),
+            simulator.registers.get(Register::IP),
+            simulator.registers.get(Register::SP),
+        )
+    }
+
+    #[test]
+    fn int_pushes_flags_cs_ip_and_iret_restores_them() {
+        let mut simulator = with_vector(0x21);
+        step(&mut simulator, &[0xCD, 0x21], &[], "CTI").unwrap();
+
+        assert_eq!(cs_ip_sp(&simulator), (0x5678, 0x1234, 0x00FA));
+        // IF and TF are cleared for the handler, the other flags are kept
+        assert_eq!(simulator.registers.flags_string(), "C");
+        let stack = 0x30000;
+        assert_eq!(simulator.memory.read_u16(stack + 0xFE), 0xF303);
+        assert_eq!(simulator.memory.read_u16(stack + 0xFC), 0x0000);
+        assert_eq!(simulator.memory.read_u16(stack + 0xFA), 0x0102);
+
+        simulator.memory.write_u8(0x56780 + 0x1234, 0xCF);
+        let iret = simulator.fetch().unwrap();
+        simulator.execute(&iret).unwrap();
+        assert_eq!(cs_ip_sp(&simulator), (0x0000, 0x0102, 0x0100));
+        assert_eq!(simulator.registers.flags_string(), "CTI");
+    }
+
+    #[test]
+    fn into_only_fires_on_overflow() {
+        let mut simulator = with_vector(4);
+        step(&mut simulator, &[0xCE], &[], "").unwrap();
+        assert_eq!(cs_ip_sp(&simulator), (0x0000, 0x0101, 0x0100));
+
+        step(&mut simulator, &[0xCE], &[], "O").unwrap();
+        assert_eq!(cs_ip_sp(&simulator), (0x5678, 0x1234, 0x00FA));
+    }
+
+    #[test]
+    fn host_handler_takes_precedence_over_the_vector_table() {
+        let mut simulator = with_vector(3);
+        let raised = count_interrupts(&mut simulator, 3);
+        step(&mut simulator, &[0xCC], &[], "I").unwrap();
+        assert_eq!(raised.get(), 1);
+        // Nothing is pushed and execution continues after the INT3
+        assert_eq!(cs_ip_sp(&simulator), (0x0000, 0x0101, 0x0100));
+        assert_eq!(simulator.registers.flags_string(), "I");
+
+        simulator.clear_interrupt_handler(3);
+        step(&mut simulator, &[0xCC], &[], "I").unwrap();
+        assert_eq!(cs_ip_sp(&simulator), (0x5678, 0x1234, 0x00FA));
+    }
+
+    #[test]
+    fn vectors_without_a_handler_are_unhandled() {
+        let unhandled = |result: Result<(), ExecError>| match result {
+            Err(ExecError::UnhandledInterrupt { vector, .. }) => Some(vector),
+            _ => None,
+        };
+
+        // An entry still at 0000:0000
+        let mut simulator = Simulator::new();
+        assert_eq!(
+            unhandled(step(&mut simulator, &[0xCD, 0x10], &[], "")),
+            Some(0x10)
+        );
+
+        // An entry covered by the program, which would otherwise jump to 0000:0010
+        let mut program = vec![0xCD, 0x21];
+        program.extend(0x10..0xA0);
+        simulator.load_program(&program, 0);
+        let instruction = simulator.fetch().unwrap();
+        assert_eq!(unhandled(simulator.execute(&instruction)), Some(0x21));
+
+        // The same entry is honoured once the program is loaded above the table
+        let mut simulator = with_vector(0x21);
+        simulator.load_program(&program, 0x400);
+        let instruction = simulator.fetch().unwrap();
+        simulator.execute(&instruction).unwrap();
+        assert_eq!(cs_ip_sp(&simulator), (0x5678, 0x1234, 0x00FA));
+    }
+}
@@ -1,12 +1,5 @@
 use crate::opcodes::Opcode;
-use crate::registers::REGISTERS;
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::{self, Read};
-use std::path::Path;
-
-// Module-level DEBUG constant
-pub const DEBUG: bool = true;
 
 #[derive(Default)]
 pub struct BitTrie {
@@ -42,93 +35,10 @@ impl BitTrie {
     }
 }
 
-pub struct Reader {
-    buffer: Vec<u8>,
-    pos: usize,
-}
-
-impl Reader {
-    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
-        let mut file = File::open(path)?;
-        let mut buffer = Vec::new();
-        file.read_to_end(&mut buffer)?;
-        Ok(Self { buffer, pos: 0 })
-    }
-
-    pub fn peek(&self) -> Option<u8> {
-        self.buffer.get(self.pos).copied()
-    }
-
-    pub fn read_n(&mut self, n: usize) -> Option<&[u8]> {
-        if self.pos + n <= self.buffer.len() {
-            let chunk = &self.buffer[self.pos..self.pos + n];
-            self.pos += n;
-            Some(chunk)
-        } else {
-            None
-        }
-    }
-}
-
-pub trait IteratorExt: Iterator<Item = u8> {
-    fn next_or_exit(&mut self, context: &str) -> u8 {
-        self.next().unwrap_or_else(|| {
-            eprintln!("Unexpected end of file while reading {}", context);
-            std::process::exit(1);
-        })
-    }
-}
-
-impl<I: Iterator<Item = u8>> IteratorExt for I {}
-
-impl Iterator for Reader {
-    type Item = u8;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.pos < self.buffer.len() {
-            let byte = self.buffer[self.pos];
-            self.pos += 1;
-            Some(byte)
-        } else {
-            None
-        }
-    }
-}
-
-pub fn debug(args: std::fmt::Arguments) {
-    if DEBUG {
-        println!("{}", args);
-    }
-}
-
-pub fn debug_bytes(bytes: &[u8]) {
-    debug(format_args!(
-        "; Processing bytes: [{}]",
-        bytes
-            .iter()
-            .map(|b| format!("{:08b}", b))
-            .collect::<Vec<_>>()
-            .join(", ")
-    ));
-}
-
-pub fn read_file(path: &str) -> Result<Vec<u8>, String> {
-    use std::fs;
-    fs::read(path).map_err(|e| format!("Failed to read file '{}': {}", path, e))
-}
-
-pub fn print_memory_16bit(mem: &[u8]) {
-    assert!(mem.len() % 2 == 0, "Memory length must be divisible by 2");
-
-    for (i, chunk) in mem.chunks(2).enumerate() {
-        println!("; R{i}: {:08b} {:08b}", chunk[1], chunk[0]);
-        //let word = u16::from_le_bytes([chunk[0], chunk[1]]);
-        //println!("R{i}: {:016b}", word);
-    }
-}
-
-pub fn print_memory_hex(mem: &[u8]) {
-    for (i, chunk) in mem.chunks(2).enumerate() {
-        println!("; {}: {:02x} {:02x}", REGISTERS[1][i], chunk[1], chunk[0]);
-    }
+pub fn format_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
 }
@@ -6,6 +6,11 @@
 # 2. Runs the disassembler on the assembled binary
 # 3. Reassembles the disassembler output
 # 4. Compares the original and reassembled binaries
+#
+# Files in tests/recursive/ embed data that linear sweep would decode as code,
+# so they are disassembled with --recursive. Extra disassembler options for the
+# linear pass can be passed through DISASM_ARGS, e.g.
+# DISASM_ARGS=--recursive ./test_disassembler.sh
 
 
 # Colors for output
@@ -47,13 +52,11 @@ failed=0
 echo -e "${YELLOW}Running disassembler tests...${NC}"
 echo "=================================="
 
-# Process each .asm file in tests directory
-for asm_file in "$TESTS_DIR"/*.asm; do
-    if [ ! -f "$asm_file" ]; then
-        echo -e "${RED}No .asm files found in $TESTS_DIR${NC}"
-        exit 1
-    fi
-    
+# Assembles $1, disassembles it with the remaining arguments and reassembles the output
+run_test() {
+    local asm_file="$1"
+    shift
+
     filename=$(basename "$asm_file" .asm)
     echo -e "${YELLOW}Testing: $filename${NC}"
     
@@ -63,7 +66,7 @@ for asm_file in "$TESTS_DIR"/*.asm; do
     
     # Step 2: Run disassembler on the binary
     echo "  Running disassembler..."
-    "$DISASSEMBLER" "$TEMP_DIR/${filename}_original.bin" > "$TEMP_DIR/${filename}_disassembled.asm" 2>/dev/null
+    "$DISASSEMBLER" disasm "$@" "$TEMP_DIR/${filename}_original.bin" > "$TEMP_DIR/${filename}_disassembled.asm" 2>/dev/null
     
     # Step 3: Assemble the disassembler output
     echo "  Reassembling disassembler output..."
@@ -90,6 +93,20 @@ for asm_file in "$TESTS_DIR"/*.asm; do
     fi
     
     echo ""
+}
+
+# Process each .asm file in tests directory
+for asm_file in "$TESTS_DIR"/*.asm; do
+    if [ ! -f "$asm_file" ]; then
+        echo -e "${RED}No .asm files found in $TESTS_DIR${NC}"
+        exit 1
+    fi
+    run_test "$asm_file" $DISASM_ARGS
+done
+
+# Only recursive descent can tell the embedded data apart from code
+for asm_file in "$TESTS_DIR"/recursive/*.asm; do
+    [ -f "$asm_file" ] && run_test "$asm_file" --recursive
 done
 
 # Print summary
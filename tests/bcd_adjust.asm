@@ -0,0 +1,22 @@
+; ========================================================================
+; DAA / DAS / AAA / AAS / AAM / AAD, including non-decimal bases
+; ========================================================================
+
+bits 16
+
+mov al, 21
+add al, 39
+daa
+sub al, 21
+das
+mov ax, 9
+add al, 5
+aaa
+sub al, 7
+aas
+mov al, 63
+aam
+aad
+aam 16
+aad 16
+aad 2
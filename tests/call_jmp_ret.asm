@@ -0,0 +1,31 @@
+; ========================================================================
+; CALL / JMP / RET in direct, indirect, near and far encodings
+; ========================================================================
+
+bits 16
+
+start:
+mov sp, 4096
+call routine
+call bx
+call [bx]
+call word [bp + 4]
+call far [bx]
+call far [4096]
+call 4660:22136
+jmp short done
+jmp near routine
+jmp ax
+jmp [bx + 2]
+jmp far [bx + si]
+jmp 4096:0
+
+routine:
+mov bx, 2
+ret
+ret 4
+retf
+retf 2
+
+done:
+jmp start
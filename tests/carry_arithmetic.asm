@@ -0,0 +1,43 @@
+; ========================================================================
+; ADC / SBB / INC / DEC / NEG, including the one-byte INC/DEC forms
+; ========================================================================
+
+bits 16
+
+add ax, 1
+adc dx, 0
+adc ax, bx
+adc al, [bx]
+adc [bp + si + 8], cx
+adc al, 5
+adc ax, 256
+adc byte [bx], 1
+adc cx, -1
+
+sub ax, 2
+sbb dx, 0
+sbb bx, cx
+sbb al, [bx]
+sbb al, 1
+sbb ax, 4096
+sbb bl, -1
+sbb word [bx + si], 10
+
+inc ax
+inc di
+inc sp
+dec ax
+dec di
+dec bp
+inc al
+inc bh
+inc byte [bx]
+inc word [bx + 2]
+dec dl
+dec byte [si - 1]
+dec word [4096]
+
+neg al
+neg di
+neg word [bx]
+neg byte [bp + di + 300]
@@ -0,0 +1,55 @@
+; ========================================================================
+; Every conditional jump and the LOOP family
+; ========================================================================
+
+bits 16
+
+mov cx, 5
+mov ax, 0
+count:
+add ax, 2
+loop count
+cmp ax, 20
+jl less
+mov bx, 1
+less:
+jle less_equal
+less_equal:
+jb below
+below:
+jbe below_equal
+below_equal:
+jp parity
+parity:
+jo overflow
+overflow:
+js sign
+sign:
+jnl not_less
+not_less:
+jg greater
+greater:
+jnb not_below
+not_below:
+ja above
+above:
+jnp not_parity
+not_parity:
+jno not_overflow
+not_overflow:
+jns not_sign
+not_sign:
+je equal
+equal:
+jne not_equal
+not_equal:
+mov cx, 3
+zero_loop:
+cmp cx, 2
+loopz zero_loop
+nonzero_loop:
+cmp cx, 0
+loopnz nonzero_loop
+jcxz done
+mov dx, 7
+done:
@@ -0,0 +1,31 @@
+; ========================================================================
+; XCHG / XLAT / LEA / LDS / LES / LAHF / SAHF / PUSHF / POPF / CBW / CWD
+; ========================================================================
+
+bits 16
+
+xchg cl, bl
+xchg ax, [bx]
+xchg dh, [bp + si + 8]
+xchg ax, cx
+xchg ax, di
+xchg ax, sp
+
+xlatb
+es xlatb
+
+lea bx, [bp + 4]
+lea si, [bx + di]
+lea di, [bx + si - 200]
+lea ax, [4096]
+lds si, [bx]
+lds bx, [bp + 6]
+les di, [bx + 2]
+les ax, [4096]
+
+lahf
+sahf
+pushf
+popf
+cbw
+cwd
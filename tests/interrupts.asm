@@ -0,0 +1,13 @@
+; ========================================================================
+; INT / INT3 / INTO / IRET
+; ========================================================================
+
+bits 16
+
+int 33
+int 16
+int 128
+int 3
+int3
+into
+iret
@@ -90,32 +90,32 @@ cmp al, ah
 cmp ax, 1000
 cmp al, -30
 cmp al, 9
-; 
-; test_label0:
-; jnz test_label1
-; jnz test_label0
-; test_label1:
-; jnz test_label0
-; jnz test_label1
-; 
-; label:
-; je label
-; jl label
-; jle label
-; jb label
-; jbe label
-; jp label
-; jo label
-; js label
-; jne label
-; jnl label
-; jg label
-; jnb label
-; ja label
-; jnp label
-; jno label
-; jns label
-; loop label
-; loopz label
-; loopnz label
-; jcxz label
+
+test_label0:
+jnz test_label1
+jnz test_label0
+test_label1:
+jnz test_label0
+jnz test_label1
+
+label:
+je label
+jl label
+jle label
+jb label
+jbe label
+jp label
+jo label
+js label
+jne label
+jnl label
+jg label
+jnb label
+ja label
+jnp label
+jno label
+jns label
+loop label
+loopz label
+loopnz label
+jcxz label
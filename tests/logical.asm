@@ -0,0 +1,42 @@
+; ========================================================================
+; AND / OR / XOR / TEST / NOT in register, memory, immediate and
+; accumulator encodings
+; ========================================================================
+
+bits 16
+
+and ax, bx
+and al, [bx]
+and [bp + di + 4], cx
+and al, 240
+and ax, 3855
+and word [bx], 15
+and byte [si - 2], 127
+
+or cl, bl
+or dx, [bx + si + 1000]
+or al, 1
+or ax, 256
+or byte [bx + si], 128
+or bx, 4660
+
+xor ax, ax
+xor [bx + si], bx
+xor dh, [si]
+xor al, -1
+xor ax, 21845
+xor word [di], 1
+xor cx, -2
+
+test bl, al
+test [bx], ax
+test al, 1
+test ax, 32768
+test bl, 1
+test word [bx], 4096
+test byte [bp + 6], 8
+
+not al
+not cx
+not word [bx]
+not byte [bp - 1]
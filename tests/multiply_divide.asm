@@ -0,0 +1,26 @@
+; ========================================================================
+; MUL / IMUL / DIV / IDIV on byte and word operands
+; ========================================================================
+
+bits 16
+
+mul bl
+mul al
+mul cx
+mul byte [bx]
+mul word [bp + 4]
+
+imul dh
+imul ax
+imul word [bx + 2]
+imul byte [si - 8]
+
+div cl
+div bx
+div byte [4096]
+div word [bx + di]
+
+idiv ah
+idiv cx
+idiv word [bp + si + 300]
+idiv byte [di]
@@ -0,0 +1,112 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use cpu_parser::{PortBus, Register, Simulator};
+
+#[derive(Debug, PartialEq)]
+enum Access {
+    Read { port: u16, w: u8 },
+    Write { port: u16, w: u8, value: u16 },
+}
+
+// Records every access and answers reads with a value derived from the port,
+// so each IN can be told apart
+struct Recorder {
+    log: Rc<RefCell<Vec<Access>>>,
+}
+
+impl PortBus for Recorder {
+    fn read(&mut self, port: u16, w: u8) -> u16 {
+        self.log.borrow_mut().push(Access::Read { port, w });
+        if w == 1 {
+            0xA500 | (port & 0xFF)
+        } else {
+            (port & 0xFF) ^ 0x5A
+        }
+    }
+
+    fn write(&mut self, port: u16, w: u8, value: u16) {
+        self.log.borrow_mut().push(Access::Write { port, w, value });
+    }
+}
+
+fn simulator_with_recorder() -> (Simulator, Rc<RefCell<Vec<Access>>>) {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut simulator = Simulator::new();
+    simulator.set_port_bus(Box::new(Recorder {
+        log: Rc::clone(&log),
+    }));
+    (simulator, log)
+}
+
+fn step(simulator: &mut Simulator) {
+    let instruction = simulator.fetch().unwrap();
+    simulator.execute(&instruction).unwrap();
+}
+
+#[test]
+fn fixed_ports() {
+    let (mut simulator, log) = simulator_with_recorder();
+    // in al, 96 / in ax, 97 / out 233, al / out 97, ax
+    simulator.load_program(&[0xE4, 0x60, 0xE5, 0x61, 0xE6, 0xE9, 0xE7, 0x61], 0x100);
+
+    step(&mut simulator);
+    assert_eq!(simulator.registers.get(Register::AX), 0x003A);
+    step(&mut simulator);
+    assert_eq!(simulator.registers.get(Register::AX), 0xA561);
+    step(&mut simulator);
+    step(&mut simulator);
+
+    assert_eq!(
+        *log.borrow(),
+        [
+            Access::Read { port: 0x60, w: 0 },
+            Access::Read { port: 0x61, w: 1 },
+            Access::Write {
+                port: 0xE9,
+                w: 0,
+                value: 0x61
+            },
+            Access::Write {
+                port: 0x61,
+                w: 1,
+                value: 0xA561
+            },
+        ]
+    );
+}
+
+#[test]
+fn port_in_dx() {
+    let (mut simulator, log) = simulator_with_recorder();
+    // mov dx, 1016 / in al, dx / out dx, al / in ax, dx / out dx, ax
+    simulator.load_program(&[0xBA, 0xF8, 0x03, 0xEC, 0xEE, 0xED, 0xEF], 0x100);
+    simulator.registers.set(Register::AX, 0x1234);
+
+    step(&mut simulator);
+    step(&mut simulator);
+    // A byte IN only replaces AL
+    assert_eq!(simulator.registers.get(Register::AX), 0x12A2);
+    step(&mut simulator);
+    step(&mut simulator);
+    assert_eq!(simulator.registers.get(Register::AX), 0xA5F8);
+    step(&mut simulator);
+
+    assert_eq!(
+        *log.borrow(),
+        [
+            Access::Read { port: 0x3F8, w: 0 },
+            Access::Write {
+                port: 0x3F8,
+                w: 0,
+                value: 0xA2
+            },
+            Access::Read { port: 0x3F8, w: 1 },
+            Access::Write {
+                port: 0x3F8,
+                w: 1,
+                value: 0xA5F8
+            },
+        ]
+    );
+}
@@ -0,0 +1,17 @@
+; ========================================================================
+; IN / OUT through fixed ports and the port in DX
+; ========================================================================
+
+bits 16
+
+in al, 96
+in ax, 97
+in al, 255
+out 96, al
+out 97, ax
+out 233, al
+mov dx, 1016
+in al, dx
+in ax, dx
+out dx, al
+out dx, ax
@@ -0,0 +1,28 @@
+; ========================================================================
+; CLC / STC / CMC / CLD / STD / CLI / STI / HLT / WAIT / LOCK / NOP / ESC
+; ========================================================================
+
+bits 16
+
+clc
+stc
+cmc
+cld
+std
+cli
+sti
+nop
+wait
+lock xchg ax, [bx]
+lock add [bp + 2], cx
+lock rep movsb
+
+; ESC (D8-DF) has no nasm mnemonic, so the disassembler writes it back as bytes:
+; register forms name an 8087 stack register, memory forms carry a displacement
+db 0xd8, 0xc1
+db 0xd9, 0x07
+db 0xdb, 0x47, 0x10
+db 0xdd, 0x9e, 0x34, 0x12
+db 0x26, 0xdc, 0x06, 0x00, 0x01
+db 0xdf, 0xff
+hlt
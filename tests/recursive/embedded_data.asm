@@ -0,0 +1,29 @@
+; ========================================================================
+; Data embedded between reachable code, for disasm --recursive
+; ========================================================================
+;
+; Linear sweep decodes the bytes after the first jump as code and stops at
+; ff ff, which is no valid instruction. Recursive descent never reaches them
+; and keeps them, the table and the padding byte as db.
+
+bits 16
+
+    mov cx, 3
+    jmp short over
+    db 0xff, 0xff
+
+over:
+    call countdown
+    jmp near done
+
+table:
+    dw 0x1234, 0xabcd
+
+countdown:
+    dec cx
+    jnz countdown
+    ret
+    db 0xd6
+
+done:
+    hlt
@@ -0,0 +1,23 @@
+; ========================================================================
+; Segment register moves and segment-override prefixes
+; ========================================================================
+
+bits 16
+
+mov ax, 8192
+mov ds, ax
+mov es, ax
+mov ss, [bp + 4]
+mov cx, es
+mov [bx + si], cs
+mov bx, 16
+mov word es:[bx], 5
+mov ax, es:[bx]
+mov al, cs:[bx + si + 2]
+mov ss:[bp + di - 8], dx
+mov ax, ds:[bp]
+mov ax, ss:[16]
+mov es:[16], al
+add ax, es:[bx]
+push word es:[bx + 2]
+pop word ss:[bp]
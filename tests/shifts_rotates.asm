@@ -0,0 +1,37 @@
+; ========================================================================
+; SHL / SHR / SAR / ROL / ROR / RCL / RCR by 1 and by CL
+; ========================================================================
+
+bits 16
+
+shl ax, 1
+shl ax, cl
+shl bl, 1
+shl word [bx], 1
+shl word [4096], cl
+
+shr al, 1
+shr dx, cl
+shr word [bx], cl
+shr byte [si + 2], 1
+
+sar al, cl
+sar cx, 1
+sar byte [bp + 4], 1
+sar word [bp + di - 6], cl
+
+rol cl, 1
+rol ax, cl
+rol byte [bx + si], 1
+
+ror cx, 1
+ror dl, cl
+ror word [di], cl
+
+rcl bl, cl
+rcl si, 1
+rcl word [bx + 1000], 1
+
+rcr bx, cl
+rcr ah, 1
+rcr byte [bx], cl
@@ -0,0 +1,27 @@
+; ========================================================================
+; PUSH / POP in register, segment register and r/m encodings
+; ========================================================================
+
+bits 16
+
+mov sp, 256
+mov ax, 4660
+push ax
+pop bx
+push cs
+pop ds
+push es
+pop es
+push ss
+pop ss
+push ds
+push word [bx]
+push word [bp + 4]
+push word [bx + si - 300]
+push word [4418]
+pop word [128]
+pop word [bx + 2]
+pop word [bp + di]
+pop cx
+push sp
+pop dx
@@ -0,0 +1,31 @@
+; ========================================================================
+; MOVS / CMPS / SCAS / LODS / STOS with and without REP prefixes
+; ========================================================================
+
+bits 16
+
+movsb
+movsw
+cmpsb
+cmpsw
+scasb
+scasw
+lodsb
+lodsw
+stosb
+stosw
+
+rep movsb
+rep movsw
+rep stosb
+rep stosw
+rep lodsb
+repe cmpsb
+repe cmpsw
+repe scasw
+repne cmpsw
+repne scasb
+
+es movsb
+cs lodsw
+rep es movsw